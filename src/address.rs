@@ -1,45 +1,91 @@
-use crate::util;
+use crate::{
+  network::Network,
+  util::{self, Scheme, SignerSpec},
+};
 use anyhow::Result;
 use polymesh_api::client::{
-  sp_core::crypto::{Ss58AddressFormatRegistry, Ss58Codec},
+  sp_core::crypto::{Ss58AddressFormat, Ss58Codec},
   Signer,
 };
+use serde::Serialize;
+use std::fmt;
+
+/// The public address and key of a signing account
+#[derive(Serialize, Clone, Debug)]
+pub struct AddressReport {
+  pub address: String,
+  pub scheme: Scheme,
+  /// Public key as a hexadecimal string with an `0x` prefix
+  pub public_key: String,
+}
 
-fn signer_ss58check(signer: &impl Signer, mainnet: bool) -> String {
-  if mainnet {
-    signer
-      .account()
-      .to_ss58check_with_version(Ss58AddressFormatRegistry::PolymeshAccount.into())
-  } else {
-    signer.account().to_ss58check()
+impl fmt::Display for AddressReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.address)
   }
 }
 
-/// Generate a Polymesh public address using a 32-byte private key given as a hexadecimal string
-pub fn private_key_to_ss58check(priv_key: &str, mainnet: bool) -> Result<String> {
-  let signer = util::pairsigner_from_private_key(priv_key)?;
-  Ok(signer_ss58check(&signer, mainnet))
+/// SS58-format the account of a signer, using the address prefix of `network`
+pub fn signer_ss58check(signer: &impl Signer, network: &Network) -> String {
+  signer
+    .account()
+    .to_ss58check_with_version(Ss58AddressFormat::custom(network.ss58_prefix))
 }
 
-pub fn mnemonic_to_ss58check(
-  mnemonic: &str,
-  mainnet: bool,
-  password_override: Option<&str>,
-) -> Result<String> {
-  let signer = util::pairsigner_from_mnemonic(mnemonic, password_override)?;
-  Ok(signer_ss58check(&signer, mainnet))
+/// Generate a Polymesh public address for a signing account, given as a 32-byte hexadecimal
+/// private key, a BIP39 mnemonic or a keystore alias, along with its scheme and public key
+pub fn report(signer: &SignerSpec, network: &Network) -> Result<AddressReport> {
+  let signer = util::signer(signer)?;
+  Ok(AddressReport {
+    address: signer_ss58check(&signer, network),
+    scheme: signer.scheme(),
+    public_key: String::from("0x") + &hex::encode(signer.public_key()),
+  })
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::util::SignerSource;
+  use polymesh_api::client::{sp_core::hashing::blake2_256, AccountId};
 
   #[test]
   fn it_gets_the_correct_address() {
     let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
-    let mainnet = false;
-    let addr = private_key_to_ss58check(signing_addr, mainnet).unwrap();
+    let network = Network::testnet();
+    let signer = SignerSpec::from(SignerSource::PrivateKey(String::from(signing_addr)));
+    let addr = report(&signer, &network).unwrap().address;
     let expected = String::from("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt");
     assert_eq!(addr, expected);
   }
+
+  #[test]
+  fn it_gets_a_different_address_for_each_scheme() {
+    let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let signer = |scheme| SignerSpec {
+      scheme: Some(scheme),
+      ..SignerSpec::from(SignerSource::PrivateKey(String::from(signing_addr)))
+    };
+    let network = Network::testnet();
+    let sr25519 = report(&signer(Scheme::Sr25519), &network).unwrap().address;
+    let ed25519 = report(&signer(Scheme::Ed25519), &network).unwrap().address;
+    let ecdsa = report(&signer(Scheme::Ecdsa), &network).unwrap().address;
+    assert_eq!(sr25519, "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt");
+    assert_ne!(ed25519, sr25519);
+    assert_ne!(ecdsa, sr25519);
+    assert_ne!(ecdsa, ed25519);
+  }
+
+  #[test]
+  fn it_hashes_the_ecdsa_public_key_into_the_account() {
+    let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let spec = SignerSpec {
+      scheme: Some(Scheme::Ecdsa),
+      ..SignerSpec::from(SignerSource::PrivateKey(String::from(signing_addr)))
+    };
+    let signer = util::signer(&spec).unwrap();
+    let public_key = signer.public_key();
+    assert_eq!(public_key.len(), 33); // compressed secp256k1 public key
+    assert_eq!(signer.account(), AccountId::from(blake2_256(&public_key)));
+  }
 }
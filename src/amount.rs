@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Decimal places of POLYX, i.e. 1 POLYX is 10^6 μPOLYX
+pub const DECIMALS: u32 = 6;
+
+/// Unit suffixes an amount may be given in, with the decimal places each one allows
+const UNITS: [(&str, u32); 4] = [
+  ("mPOLYX", DECIMALS - 3),
+  ("uPOLYX", 0),
+  ("μPOLYX", 0),
+  ("POLYX", DECIMALS),
+];
+
+/// Reason an amount failed to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+  /// Not a plain decimal number, e.g. empty, `NaN`, `1e6` or `1,000`
+  NotDecimal(String),
+  Negative,
+  /// More decimal places than the unit allows
+  TooPrecise {
+    unit: &'static str,
+    decimals: u32,
+  },
+  /// More μPOLYX than fit in a u128
+  Overflow,
+}
+
+impl fmt::Display for AmountError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::NotDecimal(amount) => write!(f, "{amount} is not a decimal amount"),
+      Self::Negative => write!(f, "amount must not be negative"),
+      Self::TooPrecise { unit, decimals: 0 } => {
+        write!(f, "amounts in {unit} must be whole numbers")
+      }
+      Self::TooPrecise { unit, decimals } => {
+        write!(
+          f,
+          "amounts in {unit} have at most {decimals} decimal places"
+        )
+      }
+      Self::Overflow => write!(f, "amount is too large"),
+    }
+  }
+}
+
+impl std::error::Error for AmountError {}
+
+/// An exact amount of POLYX, held in μPOLYX
+#[derive(
+  Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct Balance(u128);
+
+impl Balance {
+  pub fn from_micro(micro: u128) -> Self {
+    Self(micro)
+  }
+
+  /// The amount in μPOLYX, as the chain counts it
+  pub fn micro(self) -> u128 {
+    self.0
+  }
+
+  /// Parse a whole number of μPOLYX, as given with `--raw`
+  pub fn parse_raw(s: &str) -> Result<Self, AmountError> {
+    parse_decimal(s.trim(), "μPOLYX", 0).map(Self)
+  }
+
+  /// The amount in POLYX, with as many decimal places as it needs
+  pub fn to_decimal_string(self) -> String {
+    let unit = 10u128.pow(DECIMALS);
+    let (whole, fraction) = (self.0 / unit, self.0 % unit);
+    if fraction == 0 {
+      return whole.to_string();
+    }
+    let fraction = format!("{fraction:0width$}", width = DECIMALS as usize);
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+  }
+
+  /// The amount in POLYX, followed by the token symbol
+  pub fn with_symbol(self, symbol: &str) -> String {
+    format!("{} {symbol}", self.to_decimal_string())
+  }
+}
+
+impl FromStr for Balance {
+  type Err = AmountError;
+
+  /// Parse a decimal amount, in POLYX unless suffixed with `POLYX`, `mPOLYX`, `uPOLYX` or `μPOLYX`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    let (number, unit, decimals) = UNITS
+      .iter()
+      .find_map(|&(unit, decimals)| {
+        s.strip_suffix(unit)
+          .map(|number| (number.trim_end(), unit, decimals))
+      })
+      .unwrap_or((s, "POLYX", DECIMALS));
+    // A unit's allowed decimal places reach down to exactly one μPOLYX
+    parse_decimal(number, unit, decimals).map(Self)
+  }
+}
+
+impl fmt::Display for Balance {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.with_symbol("POLYX"))
+  }
+}
+
+/// Parse a non-negative decimal number with at most `decimals` decimal places into an integer
+/// count of 10^-`decimals`
+fn parse_decimal(number: &str, unit: &'static str, decimals: u32) -> Result<u128, AmountError> {
+  if number.starts_with('-') {
+    return Err(AmountError::Negative);
+  }
+  let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+  let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+  if whole.is_empty() && fraction.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+    return Err(AmountError::NotDecimal(String::from(number)));
+  }
+  // Trailing zeros don't add precision
+  let fraction = fraction.trim_end_matches('0');
+  if fraction.len() > decimals as usize {
+    return Err(AmountError::TooPrecise { unit, decimals });
+  }
+  let digits = format!("{whole}{fraction:0<width$}", width = decimals as usize);
+  let digits = digits.trim_start_matches('0');
+  if digits.is_empty() {
+    return Ok(0);
+  }
+  digits.parse().map_err(|_| AmountError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_polyx_exactly() {
+    assert_eq!("1".parse(), Ok(Balance(1_000_000)));
+    assert_eq!("0.000001".parse(), Ok(Balance(1)));
+    assert_eq!("1234567.123456".parse(), Ok(Balance(1_234_567_123_456)));
+    assert_eq!(".5".parse(), Ok(Balance(500_000)));
+    assert_eq!("2.500000".parse(), Ok(Balance(2_500_000)));
+  }
+
+  #[test]
+  fn it_parses_unit_suffixes() {
+    assert_eq!("1.5 POLYX".parse(), Ok(Balance(1_500_000)));
+    assert_eq!("1.5mPOLYX".parse(), Ok(Balance(1_500)));
+    assert_eq!("42 uPOLYX".parse(), Ok(Balance(42)));
+    assert_eq!("42μPOLYX".parse(), Ok(Balance(42)));
+    assert_eq!(Balance::parse_raw("42"), Ok(Balance(42)));
+  }
+
+  #[test]
+  fn it_rejects_malformed_amounts() {
+    assert_eq!("-1".parse::<Balance>(), Err(AmountError::Negative));
+    assert_eq!(
+      "0.0000001".parse::<Balance>(),
+      Err(AmountError::TooPrecise {
+        unit: "POLYX",
+        decimals: 6
+      })
+    );
+    assert!(matches!(
+      "1.5 uPOLYX".parse::<Balance>(),
+      Err(AmountError::TooPrecise { decimals: 0, .. })
+    ));
+    assert_eq!(
+      Balance::parse_raw("1.5").unwrap_err().to_string(),
+      "amounts in μPOLYX must be whole numbers"
+    );
+    for amount in ["", ".", "NaN", "inf", "1e6", "1,000", "+1", "1 DOT"] {
+      assert!(matches!(
+        amount.parse::<Balance>(),
+        Err(AmountError::NotDecimal(_))
+      ));
+    }
+    let max_polyx = u128::MAX / 1_000_000;
+    assert!(format!("{max_polyx}").parse::<Balance>().is_ok());
+    assert_eq!(
+      format!("{}", max_polyx + 1).parse::<Balance>(),
+      Err(AmountError::Overflow)
+    );
+    assert_eq!(
+      Balance::parse_raw(&format!("{}0", u128::MAX)),
+      Err(AmountError::Overflow)
+    );
+  }
+
+  #[test]
+  fn it_formats_polyx() {
+    assert_eq!(Balance(1_500_000).to_string(), "1.5 POLYX");
+    assert_eq!(Balance(1).to_decimal_string(), "0.000001");
+    assert_eq!(Balance(42_000_000).to_decimal_string(), "42");
+  }
+}
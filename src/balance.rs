@@ -1,47 +1,539 @@
-use crate::util;
-use anyhow::Result;
-use polymesh_api::{
-  client::{sp_core::crypto::Ss58Codec, AccountId},
-  Api,
+use crate::{
+  amount::Balance,
+  block::{AtBlock, BlockId},
+  chain::ChainContext,
+  network::Network,
+  output::{ErrorCode, Failure},
 };
+use anyhow::{Context, Result};
+use futures::{future::try_join_all, StreamExt};
+use polymesh_api::client::{sp_core::crypto::Ss58Codec, AccountId, BlockHash};
+use serde::Serialize;
+use sp_runtime::traits::Header as _;
+use std::{
+  fmt, fs,
+  io::{self, Read},
+};
+
+/// A lock on part of an account's balance, e.g. by staking or vesting
+#[derive(Serialize, Clone, Debug)]
+pub struct LockReport {
+  /// Lock identifier, e.g. `staking` or `vesting`
+  pub id: String,
+  pub amount: Balance,
+  /// Which kinds of withdrawal the lock prevents: `fee`, `misc` or `all`
+  pub reasons: String,
+}
+
+/// Breakdown of an account's balance. Amounts are serialized in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct BalanceReport {
+  pub address: String,
+  pub symbol: String,
+  pub free: Balance,
+  pub reserved: Balance,
+  /// Part of the free balance that can't be withdrawn for anything other than fees
+  pub misc_frozen: Balance,
+  /// Part of the free balance that can't be withdrawn to pay fees
+  pub fee_frozen: Balance,
+  /// Free balance that no lock prevents from being transferred
+  pub transferable: Balance,
+  pub locks: Vec<LockReport>,
+}
+
+impl fmt::Display for BalanceReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let symbol = &self.symbol;
+    writeln!(f, "address: {}", self.address)?;
+    writeln!(f, "free: {}", self.free.with_symbol(symbol))?;
+    writeln!(f, "reserved: {}", self.reserved.with_symbol(symbol))?;
+    writeln!(f, "misc frozen: {}", self.misc_frozen.with_symbol(symbol))?;
+    writeln!(f, "fee frozen: {}", self.fee_frozen.with_symbol(symbol))?;
+    writeln!(f, "transferable: {}", self.transferable.with_symbol(symbol))?;
+    if self.locks.is_empty() {
+      return write!(f, "locks: none");
+    }
+    write!(f, "locks:")?;
+    for lock in &self.locks {
+      let amount = lock.amount.with_symbol(symbol);
+      write!(f, "\n  {}: {amount} ({})", lock.id, lock.reasons)?;
+    }
+    Ok(())
+  }
+}
+
+impl BalanceReport {
+  /// Part of the free balance that some lock prevents from being transferred
+  pub fn locked(&self) -> Balance {
+    self.misc_frozen.max(self.fee_frozen)
+  }
+}
+
+/// Free, reserved, locked and transferable amounts of one account, or the sum over many
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BalanceSummary {
+  pub free: Balance,
+  pub reserved: Balance,
+  pub locked: Balance,
+  pub transferable: Balance,
+}
+
+impl BalanceSummary {
+  fn columns(&self) -> [String; 4] {
+    [self.free, self.reserved, self.locked, self.transferable].map(Balance::to_decimal_string)
+  }
+}
+
+impl From<&BalanceReport> for BalanceSummary {
+  fn from(report: &BalanceReport) -> Self {
+    Self {
+      free: report.free,
+      reserved: report.reserved,
+      locked: report.locked(),
+      transferable: report.transferable,
+    }
+  }
+}
+
+/// A row of a balance table
+#[derive(Serialize, Clone, Debug)]
+pub struct BalanceRow {
+  pub address: String,
+  #[serde(flatten)]
+  pub balance: BalanceSummary,
+}
+
+/// Balances of many accounts along with their totals. Amounts are serialized in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct BalanceTable {
+  pub symbol: String,
+  pub accounts: Vec<BalanceRow>,
+  pub total: BalanceSummary,
+}
+
+const COLUMNS: [&str; 5] = ["address", "free", "reserved", "locked", "transferable"];
+
+impl BalanceTable {
+  fn new(symbol: String, reports: &[BalanceReport]) -> Self {
+    let accounts: Vec<BalanceRow> = reports
+      .iter()
+      .map(|report| BalanceRow {
+        address: report.address.clone(),
+        balance: report.into(),
+      })
+      .collect();
+    let sum = |amount: fn(&BalanceSummary) -> Balance| {
+      Balance::from_micro(
+        accounts
+          .iter()
+          .map(|row| amount(&row.balance).micro())
+          .sum(),
+      )
+    };
+    let total = BalanceSummary {
+      free: sum(|b| b.free),
+      reserved: sum(|b| b.reserved),
+      locked: sum(|b| b.locked),
+      transferable: sum(|b| b.transferable),
+    };
+    Self {
+      symbol,
+      accounts,
+      total,
+    }
+  }
+
+  /// Render the accounts as CSV with a header row, amounts in POLYX
+  pub fn to_csv(&self) -> String {
+    let mut csv = COLUMNS.join(",") + "\n";
+    for row in &self.accounts {
+      let [free, reserved, locked, transferable] = row.balance.columns();
+      csv += &format!(
+        "{},{free},{reserved},{locked},{transferable}\n",
+        row.address
+      );
+    }
+    csv
+  }
+}
+
+impl fmt::Display for BalanceTable {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut rows: Vec<[String; 5]> = vec![COLUMNS.map(String::from)];
+    let total = std::iter::once((String::from("total"), &self.total));
+    for (label, balance) in self
+      .accounts
+      .iter()
+      .map(|row| (row.address.clone(), &row.balance))
+      .chain(total)
+    {
+      let [free, reserved, locked, transferable] = balance.columns();
+      rows.push([label, free, reserved, locked, transferable]);
+    }
+    let mut widths = [0; 5];
+    for row in &rows {
+      for (width, cell) in widths.iter_mut().zip(row) {
+        *width = (*width).max(cell.chars().count());
+      }
+    }
+    writeln!(f, "amounts in {}", self.symbol)?;
+    for (i, row) in rows.iter().enumerate() {
+      // Addresses are left-aligned and amounts right-aligned
+      write!(f, "{:<w$}", row[0], w = widths[0])?;
+      for (cell, width) in row.iter().zip(widths).skip(1) {
+        write!(f, "  {cell:>width$}")?;
+      }
+      if i + 1 < rows.len() {
+        writeln!(f)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Render a lock identifier, which is 8 bytes of ASCII padded with spaces (e.g. `staking `)
+fn lock_id(id: &[u8]) -> String {
+  match std::str::from_utf8(id) {
+    Ok(id) => id.trim_end_matches([' ', '\0']).to_string(),
+    Err(_) => String::from("0x") + &hex::encode(id),
+  }
+}
+
+/// Query the balance breakdown and locks of an account over an existing connection
+pub async fn account_report(chain: &ChainContext, account: AccountId) -> Result<BalanceReport> {
+  let data = chain.query().system().account(account).await?.data;
+  let locks = chain.query().balances().locks(account).await?;
+  // Locks overlap rather than add up, so the largest frozen amount is what can't be moved
+  let frozen = data.misc_frozen.max(data.fee_frozen);
+  Ok(BalanceReport {
+    address: chain.ss58check(&account),
+    symbol: chain.symbol.clone(),
+    free: Balance::from_micro(data.free),
+    reserved: Balance::from_micro(data.reserved),
+    misc_frozen: Balance::from_micro(data.misc_frozen),
+    fee_frozen: Balance::from_micro(data.fee_frozen),
+    transferable: Balance::from_micro(data.free.saturating_sub(frozen)),
+    locks: locks
+      .iter()
+      .map(|lock| LockReport {
+        id: lock_id(&lock.id),
+        amount: Balance::from_micro(lock.amount),
+        reasons: format!("{:?}", lock.reasons).to_lowercase(),
+      })
+      .collect(),
+  })
+}
+
+/// Report the balance breakdown and locks of an SS58-formatted address, at block `at` if set
+pub async fn report(
+  addr: &str,
+  network: &Network,
+  at: Option<BlockId>,
+) -> Result<AtBlock<BalanceReport>> {
+  let account = AccountId::from_string(addr)?;
+  let (chain, block) = ChainContext::connect_at(network, at).await?;
+  let report = account_report(&chain, account).await?;
+  Ok(AtBlock { report, block })
+}
 
-pub async fn free(addr: &str, mainnet: bool) -> Result<u128> {
-  let account_info = Api::new(util::url(mainnet))
-    .await?
-    .query()
-    .system()
-    .account(AccountId::from_string(addr)?)
-    .await?;
-  Ok(account_info.data.free)
+/// Query the balances of many SS58-formatted addresses concurrently over one connection, at block
+/// `at` if set
+pub async fn table(
+  addrs: &[String],
+  network: &Network,
+  at: Option<BlockId>,
+) -> Result<AtBlock<BalanceTable>> {
+  let accounts = parse_accounts(addrs)?;
+  let (chain, block) = ChainContext::connect_at(network, at).await?;
+  let reports = account_reports(&chain, &accounts).await?;
+  let report = BalanceTable::new(chain.symbol.clone(), &reports);
+  Ok(AtBlock { report, block })
 }
 
-pub async fn staked(addr: &str, mainnet: bool) -> Result<u128> {
-  let account_info = Api::new(util::url(mainnet))
-    .await?
-    .query()
-    .system()
-    .account(AccountId::from_string(addr)?)
-    .await?;
-  Ok(account_info.data.reserved)
+fn parse_accounts(addrs: &[String]) -> Result<Vec<AccountId>> {
+  addrs
+    .iter()
+    .map(|addr| AccountId::from_string(addr).with_context(|| format!("invalid address {addr}")))
+    .collect()
+}
+
+/// Query the balances of many accounts concurrently
+async fn account_reports(
+  chain: &ChainContext,
+  accounts: &[AccountId],
+) -> Result<Vec<BalanceReport>> {
+  try_join_all(
+    accounts
+      .iter()
+      .map(|&account| account_report(chain, account)),
+  )
+  .await
+}
+
+/// Signed change in each balance figure, in μPOLYX
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BalanceDelta {
+  pub free: i128,
+  pub reserved: i128,
+  pub locked: i128,
+  pub transferable: i128,
+}
+
+impl BalanceDelta {
+  fn between(before: &BalanceSummary, after: &BalanceSummary) -> Self {
+    let delta = |before: Balance, after: Balance| {
+      (after.micro() as i128).saturating_sub(before.micro() as i128)
+    };
+    Self {
+      free: delta(before.free, after.free),
+      reserved: delta(before.reserved, after.reserved),
+      locked: delta(before.locked, after.locked),
+      transferable: delta(before.transferable, after.transferable),
+    }
+  }
+}
+
+/// A change in a watched account's balance between one block and the next
+#[derive(Serialize, Clone, Debug)]
+pub struct BalanceChange {
+  pub address: String,
+  pub symbol: String,
+  /// Number of the block the change happened in
+  pub block: u32,
+  pub block_hash: String,
+  /// The balance as of the block
+  pub balance: BalanceSummary,
+  pub delta: BalanceDelta,
+}
+
+impl fmt::Display for BalanceChange {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let figures = [
+      ("free", self.delta.free, self.balance.free),
+      ("reserved", self.delta.reserved, self.balance.reserved),
+      ("locked", self.delta.locked, self.balance.locked),
+    ];
+    let changes: Vec<String> = figures
+      .iter()
+      .filter(|(_, delta, _)| *delta != 0)
+      .map(|(name, delta, balance)| {
+        let sign = if *delta < 0 { '-' } else { '+' };
+        let delta = Balance::from_micro(delta.unsigned_abs()).with_symbol(&self.symbol);
+        format!("{name} {sign}{delta} (now {})", balance.to_decimal_string())
+      })
+      .collect();
+    write!(
+      f,
+      "#{} {} {}: {}",
+      self.block,
+      self.block_hash,
+      self.address,
+      changes.join(", ")
+    )
+  }
+}
+
+/// How many blocks and balance changes a watch saw
+#[derive(Serialize, Clone, Debug)]
+pub struct WatchSummary {
+  pub blocks: u64,
+  pub changes: u64,
+}
+
+impl fmt::Display for WatchSummary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "saw {} changes in {} blocks", self.changes, self.blocks)
+  }
+}
+
+/// Watch the balances of SS58-formatted addresses, calling `on_change` for each change, block by
+/// block, on new best blocks or on finalized blocks if `finalized` is set. Blocks the subscription
+/// skips over are read too, so no change is missed. Runs until interrupted with Ctrl-C.
+pub async fn watch(
+  addrs: &[String],
+  network: &Network,
+  finalized: bool,
+  mut on_change: impl FnMut(BalanceChange),
+) -> Result<WatchSummary> {
+  let accounts = parse_accounts(addrs)?;
+  let mut chain = ChainContext::connect(network).await?;
+  let mut heads = chain.subscribe_heads(finalized).await?;
+  let mut balances: Vec<Option<BalanceSummary>> = vec![None; accounts.len()];
+  let mut last_number: Option<u32> = None;
+  let mut summary = WatchSummary {
+    blocks: 0,
+    changes: 0,
+  };
+  loop {
+    let header = tokio::select! {
+      header = heads.next() => match header {
+        Some(header) => header?,
+        None => {
+          let message = "the node closed the block header subscription";
+          return Err(Failure::new(ErrorCode::Rpc, message).into());
+        }
+      },
+      _ = tokio::signal::ctrl_c() => return Ok(summary),
+    };
+    let mut blocks = Vec::new();
+    for number in last_number.map_or(header.number, |last| last + 1)..header.number {
+      if let Some(hash) = chain.api.client().get_block_hash(number).await? {
+        blocks.push((number, hash));
+      }
+    }
+    blocks.push((
+      header.number,
+      BlockHash::from(header.hash().to_fixed_bytes()),
+    ));
+    last_number = Some(header.number);
+
+    for (number, hash) in blocks {
+      chain.set_block(hash);
+      let reports = account_reports(&chain, &accounts).await?;
+      summary.blocks += 1;
+      for (report, balance) in reports.iter().zip(&mut balances) {
+        let after = BalanceSummary::from(report);
+        // The first block only records where each balance starts
+        match balance.replace(after) {
+          Some(before) if before != after => {
+            summary.changes += 1;
+            on_change(BalanceChange {
+              address: report.address.clone(),
+              symbol: report.symbol.clone(),
+              block: number,
+              block_hash: String::from("0x") + &hex::encode(hash),
+              balance: after,
+              delta: BalanceDelta::between(&before, &after),
+            });
+          }
+          _ => {}
+        }
+      }
+    }
+  }
+}
+
+/// Read addresses one per line from a file, or from stdin if `path` is `-`, skipping blank lines
+/// and `#` comments
+pub fn read_addresses(path: &str) -> Result<Vec<String>> {
+  let contents = if path == "-" {
+    let mut contents = String::new();
+    io::stdin().read_to_string(&mut contents)?;
+    contents
+  } else {
+    fs::read_to_string(path).with_context(|| format!("could not read addresses from {path}"))?
+  };
+  Ok(parse_addresses(&contents))
+}
+
+fn parse_addresses(contents: &str) -> Vec<String> {
+  contents
+    .lines()
+    .map(|line| line.split('#').next().unwrap_or_default().trim())
+    .filter(|line| !line.is_empty())
+    .map(String::from)
+    .collect()
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  #[test]
+  fn it_renders_lock_ids() {
+    assert_eq!(lock_id(b"staking "), "staking");
+    assert_eq!(lock_id(b"vesting "), "vesting");
+    assert_eq!(lock_id(&[0xff; 8]), "0xffffffffffffffff");
+  }
+
+  fn sample_report(address: &str, free: u128, frozen: u128) -> BalanceReport {
+    BalanceReport {
+      address: String::from(address),
+      symbol: String::from("POLYX"),
+      free: Balance::from_micro(free),
+      reserved: Balance::from_micro(1_000_000),
+      misc_frozen: Balance::from_micro(frozen),
+      fee_frozen: Balance::default(),
+      transferable: Balance::from_micro(free - frozen),
+      locks: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn it_parses_address_lists() {
+    let contents =
+      "# treasury\n5Dext4xTrU8joa6LnPhPQgs6TJH1Jgydr1n2PUyRsBVzTx1A\n\n  5Abc  # ops\n";
+    assert_eq!(
+      parse_addresses(contents),
+      ["5Dext4xTrU8joa6LnPhPQgs6TJH1Jgydr1n2PUyRsBVzTx1A", "5Abc"]
+    );
+  }
+
+  #[test]
+  fn it_totals_and_exports_balance_tables() {
+    let reports = [
+      sample_report("5A", 2_500_000, 500_000),
+      sample_report("5B", 1, 0),
+    ];
+    let table = BalanceTable::new(String::from("POLYX"), &reports);
+    assert_eq!(table.total.free, Balance::from_micro(2_500_001));
+    assert_eq!(table.total.reserved, Balance::from_micro(2_000_000));
+    assert_eq!(table.total.locked, Balance::from_micro(500_000));
+    assert_eq!(table.total.transferable, Balance::from_micro(2_000_001));
+    assert_eq!(
+      table.to_csv(),
+      "address,free,reserved,locked,transferable\n5A,2.5,1,0.5,2\n5B,0.000001,1,0,0.000001\n"
+    );
+    assert_eq!(
+      table.to_string().lines().last(),
+      Some("total    2.500001         2     0.5      2.000001")
+    );
+  }
+
+  #[test]
+  fn it_describes_balance_changes() {
+    let before = BalanceSummary::from(&sample_report("5A", 2_500_000, 500_000));
+    let after = BalanceSummary::from(&sample_report("5A", 1_000_000, 500_000));
+    let change = BalanceChange {
+      address: String::from("5A"),
+      symbol: String::from("POLYX"),
+      block: 42,
+      block_hash: String::from("0xab"),
+      balance: after,
+      delta: BalanceDelta::between(&before, &after),
+    };
+    assert_eq!(change.delta.free, -1_500_000);
+    assert_eq!(change.delta.locked, 0);
+    assert_eq!(change.to_string(), "#42 0xab 5A: free -1.5 POLYX (now 1)");
+  }
+
   #[tokio::test]
-  async fn it_gets_free_balance() {
+  async fn it_gets_a_balance_report() {
     let addr = "5Dext4xTrU8joa6LnPhPQgs6TJH1Jgydr1n2PUyRsBVzTx1A";
-    let mainnet = false;
-    let res = free(addr, mainnet).await;
-    assert!(res.is_ok());
+    let network = Network::testnet();
+    let report = report(addr, &network, None).await.unwrap().report;
+    assert!(report.transferable <= report.free);
+  }
+
+  #[tokio::test]
+  async fn it_gets_balances_concurrently() {
+    let addrs = [
+      String::from("5Dext4xTrU8joa6LnPhPQgs6TJH1Jgydr1n2PUyRsBVzTx1A"),
+      String::from("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"),
+    ];
+    let table = table(&addrs, &Network::testnet(), None)
+      .await
+      .unwrap()
+      .report;
+    assert_eq!(table.accounts.len(), 2);
+    assert!(table.total.free >= table.accounts[0].balance.free);
   }
 
   #[tokio::test]
-  async fn it_gets_staked_balance() {
+  async fn it_gets_a_historical_balance_report() {
     let addr = "5Dext4xTrU8joa6LnPhPQgs6TJH1Jgydr1n2PUyRsBVzTx1A";
-    let mainnet = false;
-    let res = staked(addr, mainnet).await;
-    assert!(res.is_ok());
+    let at = Some(BlockId::Number(1));
+    let historical = report(addr, &Network::testnet(), at).await.unwrap();
+    assert_eq!(historical.block.unwrap().number, 1);
   }
 }
@@ -0,0 +1,122 @@
+use crate::output::{ErrorCode, Failure};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use polymesh_api::client::BlockHash;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// A block given by its number or its `0x`-prefixed hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockId {
+  Number(u32),
+  Hash(BlockHash),
+}
+
+impl FromStr for BlockId {
+  type Err = Failure;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      Failure::new(
+        ErrorCode::InvalidInput,
+        format!("invalid block {s}, expected a block number or a 0x-prefixed 32-byte hash"),
+      )
+    };
+    match s.strip_prefix("0x") {
+      Some(hash) => {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(hash, &mut bytes).map_err(|_| invalid())?;
+        Ok(Self::Hash(BlockHash::from(bytes)))
+      }
+      None => s.parse().map(Self::Number).map_err(|_| invalid()),
+    }
+  }
+}
+
+impl fmt::Display for BlockId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Number(number) => write!(f, "#{number}"),
+      Self::Hash(hash) => write!(f, "0x{}", hex::encode(hash)),
+    }
+  }
+}
+
+/// The block a query read its storage at
+#[derive(Serialize, Clone, Debug)]
+pub struct BlockReport {
+  pub number: u32,
+  pub hash: String,
+  /// Milliseconds since the Unix epoch, as set by the block author
+  pub timestamp: u64,
+  /// The timestamp in RFC 3339 format, in UTC
+  pub time: String,
+}
+
+impl BlockReport {
+  pub fn new(number: u32, hash: &BlockHash, timestamp: u64) -> Self {
+    Self {
+      number,
+      hash: String::from("0x") + &hex::encode(hash),
+      timestamp,
+      time: format_timestamp(timestamp),
+    }
+  }
+}
+
+impl fmt::Display for BlockReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "block #{} {} at {}", self.number, self.hash, self.time)
+  }
+}
+
+/// A report read at a given block, or at the latest block if there is none
+#[derive(Serialize, Clone, Debug)]
+pub struct AtBlock<T> {
+  #[serde(flatten)]
+  pub report: T,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub block: Option<BlockReport>,
+}
+
+impl<T: fmt::Display> fmt::Display for AtBlock<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.block {
+      Some(block) => write!(f, "{block}\n{}", self.report),
+      None => write!(f, "{}", self.report),
+    }
+  }
+}
+
+/// Render Unix time in milliseconds as an RFC 3339 timestamp
+pub fn format_timestamp(millis: u64) -> String {
+  i64::try_from(millis)
+    .ok()
+    .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+    .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+    .unwrap_or_else(|| format!("{millis}ms"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_block_numbers_and_hashes() {
+    assert_eq!("1234".parse::<BlockId>().unwrap(), BlockId::Number(1234));
+    let hash = String::from("0x") + &"ab".repeat(32);
+    assert_eq!(
+      hash.parse::<BlockId>().unwrap(),
+      BlockId::Hash(BlockHash::from([0xab; 32]))
+    );
+    for block in ["-1", "latest", "0xabcd", "ab".repeat(32).as_str()] {
+      assert!(block.parse::<BlockId>().is_err());
+    }
+  }
+
+  #[test]
+  fn it_formats_block_timestamps() {
+    let block = BlockReport::new(7, &BlockHash::from([0; 32]), 1_672_531_200_500);
+    assert_eq!(block.time, "2023-01-01T00:00:00.500Z");
+    assert!(block.to_string().starts_with("block #7 0x0000"));
+  }
+}
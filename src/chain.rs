@@ -0,0 +1,241 @@
+use crate::{
+  amount::DECIMALS,
+  block::{BlockId, BlockReport},
+  network::Network,
+  output::{ErrorCode, Failure},
+};
+use anyhow::{Context, Result};
+use jsonrpsee_core::{client::Subscription, rpc_params};
+use polymesh_api::{
+  client::{
+    sp_core::{
+      crypto::{Ss58AddressFormat, Ss58Codec},
+      H256,
+    },
+    AccountId, BlockHash,
+  },
+  Api, QueryApi,
+};
+use sp_runtime::{generic, traits::BlakeTwo256};
+
+/// Header of a Polymesh block
+pub type Header = generic::Header<u32, BlakeTwo256>;
+
+/// A connection to a node, along with what the node reports about its chain
+pub struct ChainContext {
+  pub api: Api,
+  /// Chain name, e.g. `Polymesh Testnet`
+  pub name: String,
+  pub genesis_hash: H256,
+  /// SS58 address format from the node's system properties
+  pub ss58_format: u16,
+  /// Token symbol of the network profile
+  pub symbol: String,
+  /// Genesis hash the network profile expects, if it pins one
+  expected_genesis_hash: Option<String>,
+  /// Block whose state queries read, or the latest block if unset
+  at: Option<BlockHash>,
+}
+
+impl ChainContext {
+  /// Connect to the node of `network` and read its chain name, genesis hash and address format
+  pub async fn connect(network: &Network) -> Result<Self> {
+    let api = Api::new(&network.url).await?;
+    let client = api.client();
+    let name = client.request("system_chain", rpc_params!()).await?;
+    let properties = client.get_system_properties().await?;
+    check_decimals(network, properties.token_decimals)?;
+    let ss58_format = properties.ss58_format;
+    let genesis_hash = client.get_genesis_hash();
+    Ok(Self {
+      api,
+      name,
+      genesis_hash,
+      ss58_format,
+      symbol: network.symbol.clone(),
+      expected_genesis_hash: network.genesis_hash.clone(),
+      at: None,
+    })
+  }
+
+  /// Connect to the node of `network`, and read storage at block `at` if set
+  pub async fn connect_at(
+    network: &Network,
+    at: Option<BlockId>,
+  ) -> Result<(Self, Option<BlockReport>)> {
+    let mut chain = Self::connect(network).await?;
+    let block = match at {
+      Some(id) => Some(chain.read_at(id).await?),
+      None => None,
+    };
+    Ok((chain, block))
+  }
+
+  /// Storage queries, reading the state at the block set by [`Self::read_at`] if any
+  pub fn query(&self) -> QueryApi<'_> {
+    match self.at {
+      Some(hash) => self.api.query_at(hash),
+      None => self.api.query(),
+    }
+  }
+
+  /// Hash of the block whose state queries read, if set
+  pub fn block(&self) -> Option<BlockHash> {
+    self.at
+  }
+
+  /// Read storage at the block with hash `hash` from now on
+  pub fn set_block(&mut self, hash: BlockHash) {
+    self.at = Some(hash);
+  }
+
+  /// Keys of all the storage entries starting with `prefix`, at the block set by
+  /// [`Self::read_at`] if any
+  pub async fn storage_keys(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+    const PAGE_SIZE: u32 = 1000;
+    let client = self.api.client();
+    let prefix = String::from("0x") + &hex::encode(prefix);
+    let at = self.at.map(|hash| String::from("0x") + &hex::encode(hash));
+    let mut keys: Vec<String> = Vec::new();
+    loop {
+      let start = keys.last().cloned();
+      let params = rpc_params!(&prefix, PAGE_SIZE, start, at.clone());
+      let page: Vec<String> = client.request("state_getKeysPaged", params).await?;
+      let done = page.len() < PAGE_SIZE as usize;
+      keys.extend(page);
+      if done {
+        break;
+      }
+    }
+    keys
+      .iter()
+      .map(|key| Ok(hex::decode(key.trim_start_matches("0x"))?))
+      .collect()
+  }
+
+  /// Subscribe to the headers of new best blocks, or of finalized blocks if `finalized` is set
+  pub async fn subscribe_heads(&self, finalized: bool) -> Result<Subscription<Header>> {
+    let (subscribe, unsubscribe) = if finalized {
+      (
+        "chain_subscribeFinalizedHeads",
+        "chain_unsubscribeFinalizedHeads",
+      )
+    } else {
+      ("chain_subscribeNewHeads", "chain_unsubscribeNewHeads")
+    };
+    let client = self.api.client();
+    Ok(
+      client
+        .subscribe(subscribe, rpc_params!(), unsubscribe)
+        .await?,
+    )
+  }
+
+  /// Read storage at block `id` from now on, which needs an archive node for all but recent
+  /// blocks, and report the block's number, hash and timestamp
+  pub async fn read_at(&mut self, id: BlockId) -> Result<BlockReport> {
+    let client = self.api.client();
+    let not_found = || Failure::new(ErrorCode::InvalidInput, format!("block {id} not found"));
+    let hash = match id {
+      BlockId::Number(number) => client.get_block_hash(number).await?.ok_or_else(not_found)?,
+      BlockId::Hash(hash) => hash,
+    };
+    let header = client
+      .get_block_header(Some(hash))
+      .await?
+      .ok_or_else(not_found)?;
+    self.set_block(hash);
+    let timestamp = self.query().timestamp().now().await.with_context(|| {
+      format!("state at block {id} is unavailable; is the node an archive node?")
+    })?;
+    Ok(BlockReport::new(header.number, &hash, timestamp))
+  }
+
+  /// SS58-format an account using the chain's address format
+  pub fn ss58check(&self, account: &AccountId) -> String {
+    account.to_ss58check_with_version(Ss58AddressFormat::custom(self.ss58_format))
+  }
+
+  /// Fail if the network profile expects a different chain than the node is on, so that nothing
+  /// gets signed for the wrong chain
+  pub fn ensure_expected_chain(&self) -> Result<()> {
+    match &self.expected_genesis_hash {
+      Some(expected) if !genesis_matches(expected, &self.genesis_hash) => {
+        let message = format!(
+          "node is on {} with genesis hash 0x{}, but the network profile expects {expected}",
+          self.name,
+          hex::encode(self.genesis_hash)
+        );
+        Err(Failure::new(ErrorCode::WrongChain, message).into())
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Fail unless the network profile and the node agree on the native token's decimal places, and
+/// amounts can be formatted with them
+fn check_decimals(network: &Network, token_decimals: u32) -> Result<()> {
+  let message = if u32::from(network.decimals) != token_decimals {
+    format!(
+      "network profile expects {} decimal places, but the node's token has {token_decimals}",
+      network.decimals
+    )
+  } else if token_decimals != DECIMALS {
+    format!("amounts have {DECIMALS} decimal places, but the node's token has {token_decimals}")
+  } else {
+    return Ok(());
+  };
+  Err(Failure::new(ErrorCode::Config, message).into())
+}
+
+/// Compare a hexadecimal genesis hash, with or without an `0x` prefix, to the chain's
+fn genesis_matches(expected: &str, genesis_hash: &H256) -> bool {
+  let expected = expected.strip_prefix("0x").unwrap_or(expected);
+  expected.eq_ignore_ascii_case(&hex::encode(genesis_hash))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_compares_genesis_hashes() {
+    let genesis_hash = H256([0xab; 32]);
+    assert!(genesis_matches(&"ab".repeat(32), &genesis_hash));
+    assert!(genesis_matches(
+      &(String::from("0x") + &"AB".repeat(32)),
+      &genesis_hash
+    ));
+    assert!(!genesis_matches(&"cd".repeat(32), &genesis_hash));
+  }
+
+  #[test]
+  fn it_checks_the_token_decimals() {
+    let mut network = Network::testnet();
+    assert!(check_decimals(&network, 6).is_ok());
+    assert!(check_decimals(&network, 12).is_err());
+    network.decimals = 12;
+    assert!(check_decimals(&network, 6).is_err());
+    assert!(check_decimals(&network, 12).is_err());
+  }
+
+  #[tokio::test]
+  async fn it_reads_the_chain_context_on_testnet() {
+    let chain = ChainContext::connect(&Network::testnet()).await.unwrap();
+    assert_eq!(chain.ss58_format, 42);
+    assert!(chain.ensure_expected_chain().is_ok());
+  }
+
+  #[tokio::test]
+  async fn it_reads_state_at_a_block() {
+    let mut chain = ChainContext::connect(&Network::testnet()).await.unwrap();
+    let block = chain.read_at(BlockId::Number(1)).await.unwrap();
+    assert_eq!(block.number, 1);
+    let by_hash: BlockId = block.hash.parse().unwrap();
+    assert_eq!(
+      chain.read_at(by_hash).await.unwrap().timestamp,
+      block.timestamp
+    );
+  }
+}
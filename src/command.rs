@@ -1,35 +1,117 @@
-use clap::{arg, value_parser, Command};
+use clap::{
+  arg, builder::PossibleValuesParser, builder::TypedValueParser, value_parser, Arg, Command,
+};
+
+/// Arguments selecting the account that signs, described in their help as `role`: a private key,
+/// a mnemonic or a keystore account, along with the mnemonic's password, a derivation path and
+/// the signature scheme
+fn signer_args(role: &str) -> [Arg; 6] {
+  [
+    arg!(key: -k --key <KEY>)
+      .help(format!("32-byte hexadecimal private key of {role}"))
+      .required_unless_present_any(["mnemonic", "account"]),
+    arg!(mnemonic: -m --mnemonic <MNEMONIC>)
+      .help(format!("BIP39 mnemonic phrase of {role}"))
+      .conflicts_with("key"),
+    arg!(account: --account <ALIAS>)
+      .help(format!("Alias of {role} in the keystore"))
+      .conflicts_with_all(["key", "mnemonic"]),
+    arg!(password: --password "If set, prompts for the password of the BIP39 mnemonic"),
+    arg!(derive: --derive <PATH>).help(format!("Derivation path of {role}, e.g. //polymesh//0")),
+    arg!(scheme: --scheme <SCHEME>)
+      .help(format!("Signature scheme of {role} (defaults to sr25519)"))
+      .value_parser(["sr25519", "ed25519", "ecdsa"]),
+  ]
+}
+
+/// Arguments restricting the assets, portfolios and extrinsics secondary keys may use, or a
+/// policy file setting all three
+fn permission_args() -> [Arg; 7] {
+  [
+    arg!(assets: --assets <TICKERS> "Tickers of the only assets secondary keys may use, separated by commas")
+      .value_delimiter(','),
+    arg!(except_assets: --"except-assets" <TICKERS> "Tickers of assets secondary keys may not use, separated by commas")
+      .value_delimiter(',')
+      .conflicts_with("assets"),
+    arg!(portfolios: --portfolios <PORTFOLIOS> "The only portfolios secondary keys may use, as default or numbers separated by commas")
+      .value_delimiter(','),
+    arg!(except_portfolios: --"except-portfolios" <PORTFOLIOS> "Portfolios secondary keys may not use, as default or numbers separated by commas")
+      .value_delimiter(',')
+      .conflicts_with("portfolios"),
+    arg!(extrinsics: --extrinsics <EXTRINSICS> "The only extrinsics secondary keys may call, as pallet:call, or pallet or pallet:* for all its calls, separated by commas")
+      .value_delimiter(','),
+    arg!(except_extrinsics: --"except-extrinsics" <EXTRINSICS> "Extrinsics secondary keys may not call, as pallet:call, or pallet or pallet:* for all its calls, separated by commas")
+      .value_delimiter(',')
+      .conflicts_with("extrinsics"),
+    arg!(permissions: --permissions <FILE> "JSON or YAML policy file of the secondary keys' permissions")
+      .conflicts_with_all(["assets", "except_assets", "portfolios", "except_portfolios", "extrinsics", "except_extrinsics"]),
+  ]
+}
 
 pub fn command() -> Command {
   Command::new("polymesh-cli")
     .about("Utilities for interacting with the Polymesh blockchain")
     .subcommand_required(true)
+    .args(&[
+      arg!(network: --network <NAME> "Network to connect to: mainnet, testnet, local or a profile from the config file")
+        .global(true),
+      arg!(url: --url <WS_URL> "Websocket URL of the node to connect to, overriding the network's")
+        .global(true),
+      arg!(output: --output <FORMAT> "Format of results and errors")
+        .value_parser(["text", "json", "yaml"])
+        .default_value("text")
+        .global(true),
+      arg!(wait: --wait <WHEN> "How long to wait for transactions: until accepted, included in a block or finalized")
+        .value_parser(["none", "inblock", "finalized"])
+        .default_value("inblock")
+        .global(true),
+      arg!(dry_run: --"dry-run" "Build and sign transactions, then estimate their fee and dry-run them instead of submitting them")
+        .global(true),
+      arg!(nonce: --nonce <NONCE> "Nonce of transactions: a number, auto for the account's nonce on chain, or auto-pending to also count its transactions in the pool")
+        .default_value("auto")
+        .global(true),
+      arg!(mortality: --mortality <BLOCKS> "Number of blocks transactions stay valid for, rounded up to a power of two, or immortal")
+        .default_value("immortal")
+        .global(true),
+      arg!(tip: --tip <AMOUNT> "Tip for the block author in POLYX, or with a unit, e.g. 10mPOLYX")
+        .global(true),
+    ])
 
     // Subcommand: send
     .subcommand(
       Command::new("send")
         .about("Send POLYX between accounts.")
+        .args(signer_args("the signing account"))
         .args(&[
-          arg!(key: -k --key <KEY> "32-byte hexadecimal private key of signing account")
-            .required_unless_present("mnemonic"),
-          arg!(mnemonic: -m --mnemonic <MNEMONIC> "BIP39 mnemonic phrase of signing account")
-            .conflicts_with("key"),
-          arg!(amount: -a --amount <AMOUNT> "Amount to transfer in POLYX")
-            .value_parser(value_parser!(f64))
+          arg!(amount: -a --amount <AMOUNT> "Amount to transfer in POLYX, or with a unit, e.g. 1.5mPOLYX")
+            .allow_hyphen_values(true)
             .required(true),
+          arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
           arg!(destination: -d --destination <DESTINATION> "Public SS58 address of receiving account")
             .required(true),
-          arg!(mainnet: --mainnet "If set, performs transaction on mainnet").required(false),
         ])
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+          Command::new("batch")
+            .about("Send POLYX to many accounts, listed in a CSV file of destination,amount[,memo] rows, in utility batches")
+            .args(signer_args("the signing account"))
+            .args(&[
+              arg!(file: <FILE> "CSV file of transfers, or - to read them from stdin"),
+              arg!(raw: --raw "If set, amounts are whole numbers of μPOLYX"),
+              arg!(max_per_batch: --"max-per-batch" <COUNT> "Most transfers to pack into one batch transaction")
+                .value_parser(value_parser!(u32).range(1..))
+                .default_value("50"),
+            ])
+        )
     )
 
     // Subcommand: sign
     .subcommand(
       Command::new("sign")
         .about("Sign a payload of bytes with Polymesh private key.")
+        .args(signer_args("the signing account"))
         .args(&[
-          arg!(key: -k --key <KEY> "32-byte hexadecimal private key of signing account")
-            .required(true),
           arg!(payload: -p --payload <PAYLOAD> "Payload of bytes given as a hexadecimal string")
             .required(true),
         ])
@@ -53,22 +135,32 @@ pub fn command() -> Command {
     .subcommand(
       Command::new("address")
         .about("Get a user's public address given their hexadecimal private key")
-        .args(&[
-          arg!(key: "32-byte hexadecimal private key of signing account").required_unless_present("mnemonic"),
-          arg!(mainnet: --mainnet "If set, returns mainnet address (starting with 2)").required(false),
-          arg!(mnemonic: -m --mnemonic <MNEMONIC> "Use BIP39 mnemonic rather than hexadecimal private key").conflicts_with("key"),
-        ])
+        .args(signer_args("the account"))
+        // The key is given positionally
+        .mut_arg("key", |key| key.short(None).long(None))
     )
 
     // Subcommand: balance
     .subcommand(
       Command::new("balance")
-        .about("Get a user's balance on mainnet or testnet (in μPOLYX)")
+        .about("Get a user's free, reserved, frozen and transferable balance, and its locks, or a table of many users' balances")
         .args(&[
-          arg!(address: "SS58-formatted public address (starts with 5 on testnet or 2 on mainnet)").required(true),
-          arg!(staked: -s --staked "If set, return the staked balance"),
-          arg!(mainnet: --mainnet "If set, returns mainnet address (starting with 2)").required(false),
+          arg!(addresses: [ADDRESS] ... "SS58-formatted public addresses")
+            .required_unless_present("file"),
+          arg!(file: -f --file <FILE> "File of addresses, one per line, or - to read them from stdin"),
+          arg!(csv: --csv <FILE> "Also export the balance table as CSV to this file"),
+          arg!(at: --at <BLOCK> "Read balances at this block number or 0x-prefixed block hash (needs an archive node)"),
         ])
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+          Command::new("watch")
+            .about("Print every change to the balances of some addresses, block by block, until interrupted")
+            .args(&[
+              arg!(addresses: <ADDRESS> ... "SS58-formatted public addresses to watch"),
+              arg!(finalized: --finalized "If set, watches finalized blocks rather than new best blocks"),
+            ])
+        )
     )
 
     // Subcommand: DID
@@ -76,46 +168,157 @@ pub fn command() -> Command {
       Command::new("did")
         .about("Get the DID associated with an address")
         .args(&[
-          arg!(address: "SS58-formatted public address (starts with 5 on testnet or 2 on mainnet)").required(true),
-          arg!(mainnet: --mainnet "If set, returns mainnet address (starting with 2)").required(false),
+          arg!(address: "SS58-formatted public address").required(true),
+          arg!(at: --at <BLOCK> "Read the identity at this block number or 0x-prefixed block hash (needs an archive node)"),
         ])
     )
 
     // Subcommand: secondary
     .subcommand(
       Command::new("secondary")
-        .about("Add and remove secondary keys from primary signing account.")
+        .about("List and manage the secondary keys of an identity.")
         .subcommand_required(true)
+        .subcommand(
+          Command::new("list")
+            .about("List the keys of an identity, with the permissions of each secondary key and whether they are frozen")
+            .short_flag('l')
+            .args(&[
+              arg!(who: <WHO> "0x-prefixed DID, or SS58-formatted public address of one of the identity's keys"),
+              arg!(at: --at <BLOCK> "List the keys at this block number or 0x-prefixed block hash (needs an archive node)"),
+            ])
+        )
         .subcommand(
           Command::new("add")
-            .about("Add a secondary key with authorizations to an identity (must be signed by primary key)")
+            .about("Add secondary keys with authorizations to an identity in one transaction (must be signed by primary key)")
             .short_flag('a')
+            .args(signer_args("the primary account"))
             .args(&[
-              arg!(mnemonic: -m --mnemonic <MNEMONIC> "BIP39 secret mnemonic phrase for primary account")
-                .required(true),
-              arg!(secondary_key: -s --secondary <SECONDARY> "32-byte hexadecimal private signing key of secondary")
+              arg!(secondary_key: -s --secondary <SECONDARY> ... "32-byte hexadecimal private signing key of a secondary; may be repeated")
                 .alias("who")
                 .short_alias('w')
-                .required(true),
-              arg!(expires_after: -e --expires <EXPIRY> "Set the duration (in seconds) for which secondary will have authorization")
+                .required_unless_present_any(["secondary_account", "secondaries", "signatures"]),
+              arg!(secondary_account: --"secondary-account" <ALIAS> ... "Alias of a secondary account in the keystore; may be repeated"),
+              arg!(secondaries: --secondaries <FILE> "JSON or YAML file listing secondaries by key or account, each with optional permissions"),
+              arg!(signatures: --signature <FILE> ... "Authorization signature file written by secondary auth-sign; may be repeated"),
+              arg!(expires_after: -e --expires <EXPIRY> "Set the duration (in seconds) for which secondaries will have authorization")
+                .value_parser(value_parser!(u64))
+                .required_unless_present("signatures")
+                .conflicts_with("signatures"),
+            ])
+            .args(permission_args())
+        )
+        .subcommand(
+          Command::new("auth-request")
+            .about("Request an authorization for secondary keys to sign elsewhere with auth-sign, for the primary key to add them with add --signature")
+            .args(&[
+              arg!(primary: <ADDRESS> "SS58-formatted public address of the identity's primary key"),
+              arg!(expires_after: -e --expires <EXPIRY> "Set the duration (in seconds) for which secondaries will have authorization")
                 .value_parser(value_parser!(u64))
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
+              arg!(out: --out <FILE> "Also write the request as JSON to this file"),
+            ])
+        )
+        .subcommand(
+          Command::new("auth-sign")
+            .about("Sign an authorization request with a secondary key, without connecting to a node")
+            .args(signer_args("the secondary"))
+            // Identities only accept 64-byte signatures
+            .mut_arg("scheme", |scheme| scheme.value_parser(["sr25519", "ed25519"]))
+            .args(&[
+              arg!(request: <REQUEST> "Authorization request file written by secondary auth-request"),
+              arg!(out: --out <FILE> "Also write the signature as JSON to this file"),
             ])
         )
         .subcommand(
           Command::new("remove")
             .about("Remove a secondary key from an identity (must be signed by primary key)")
             .short_flag('r')
+            .args(signer_args("the primary account"))
             .args(&[
-              arg!(mnemonic: -m --mnemonic <MNEMONIC> "BIP39 secret mnemonic phrase for primary account")
+              arg!(who: -w --who <ADDRESS> "SS58-formatted public address of secondary key")
+                .alias("secondary")
+                .short_alias('s')
                 .required(true),
+            ])
+        )
+        .subcommand(
+          Command::new("set-permissions")
+            .about("Replace the permissions of a secondary key (must be signed by primary key)")
+            .args(signer_args("the primary account"))
+            .args(&[
               arg!(who: -w --who <ADDRESS> "SS58-formatted public address of secondary key")
                 .alias("secondary")
                 .short_alias('s')
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
             ])
+            .args(permission_args())
+        )
+        .subcommand(
+          Command::new("freeze")
+            .about("Freeze all the secondary keys of an identity, so that they can't sign for it (must be signed by primary key)")
+            .args(signer_args("the primary account"))
+        )
+        .subcommand(
+          Command::new("unfreeze")
+            .about("Unfreeze all the secondary keys of an identity (must be signed by primary key)")
+            .args(signer_args("the primary account"))
+        )
+    )
+
+    // Subcommand: keys
+    .subcommand(
+      Command::new("keys")
+        .about("Manage signing accounts held in the encrypted keystore")
+        .subcommand_required(true)
+        .subcommand(
+          Command::new("add")
+            .about("Encrypt a private key (or mnemonic) and store it in the keystore under an alias")
+            .args(&[
+              arg!(alias: "Name under which to store the account").required(true),
+              arg!(mnemonic: -m --mnemonic "If set, prompts for a BIP39 mnemonic rather than a hexadecimal private key"),
+              arg!(scheme: --scheme <SCHEME> "Signature scheme of the account")
+                .value_parser(["sr25519", "ed25519", "ecdsa"])
+                .default_value("sr25519"),
+            ])
+        )
+        .subcommand(
+          Command::new("generate")
+            .about("Generate a new account from a random BIP39 mnemonic")
+            .args(&[
+              arg!(words: -w --words <WORDS> "Number of words in the mnemonic")
+                .value_parser(
+                  PossibleValuesParser::new(["12", "15", "18", "21", "24"])
+                    .map(|s| s.parse::<usize>().expect("possible values are numbers")),
+                )
+                .default_value("12"),
+              arg!(scheme: --scheme <SCHEME> "Signature scheme of the account")
+                .value_parser(["sr25519", "ed25519", "ecdsa"])
+                .default_value("sr25519"),
+              arg!(password: --password "If set, prompts for a password to protect the mnemonic"),
+              arg!(derive: --derive <PATH> "Derivation path of the account, e.g. //polymesh//0"),
+            ])
+        )
+        .subcommand(
+          Command::new("import-json")
+            .about("Import a polkadot{.js} JSON key file into the keystore")
+            .args(&[
+              arg!(file: "Path to the JSON key file").required(true),
+              arg!(alias: -n --alias <ALIAS> "Name under which to store the account (defaults to the name in the file)"),
+            ])
+        )
+        .subcommand(
+          Command::new("export-json")
+            .about("Export a keystore account as a polkadot{.js} JSON key file")
+            .arg(arg!(alias: "Name of the account to export").required(true))
+        )
+        .subcommand(
+          Command::new("list")
+            .about("List the aliases and public addresses of accounts in the keystore")
+        )
+        .subcommand(
+          Command::new("remove")
+            .about("Delete an account from the keystore")
+            .arg(arg!(alias: "Name of the account to delete").required(true))
         )
     )
 
@@ -128,86 +331,213 @@ pub fn command() -> Command {
           Command::new("validators")
             .about("Get public (SS58-formatted) addresses of current validator nodes")
             .short_flag('v')
-            .arg(arg!(mainnet: --mainnet "If set, gets operator nodes on mainnet"))
+        )
+        .subcommand(
+          Command::new("ledger")
+            .about("Get the bonded, active and unlocking stake of a stash or controller")
+            .short_flag('l')
+            .args(&[
+              arg!(address: "SS58-formatted public address of the stash or controller").required(true),
+              arg!(at: --at <BLOCK> "Read the ledger at this block number or 0x-prefixed block hash (needs an archive node)"),
+            ])
         )
         .subcommand(
           Command::new("nominate")
             .about("Declare to nominate validator nodes for the origin controller")
             .short_flag('n')
+            .args(signer_args("the controller"))
+            .mut_arg("key", |key| key.alias("controller").short_alias('c'))
             .args(&[
-              arg!(key: -k --key <CONTROLLER_KEY> "The 32-byte hexadecimal signing key of controller account")
-                .alias("controller")
-                .short_alias('c')
-                .required(true),
               arg!(validators: -v --validators <VALIDATORS> "The validator nodes to nominate (up to 24)")
                 .alias("operators")
                 .short_alias('o')
                 .num_args(1..=24)   // You can only nominate up to 24 validator nodes
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
             ])
         )
         .subcommand(
           Command::new("bond")
             .about("Take the origin account as a stash, locking up some of its balance for staking")
             .short_flag('b')
+            .args(signer_args("the stash"))
+            .mut_arg("key", |key| key.alias("stash").short_alias('s'))
             .args(&[
-              arg!(key: -k --key <STASH_KEY> "The 32-byte hexadecimal signing key of stash")
-                .alias("stash")
-                .short_alias('s')
-                .required(true),
               arg!(controller: -c --controller <CONTROLLER_ADDR> "The public address of the controller account")
                 .required(true),
-              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX) that will be locked up")
+              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX, or with a unit) that will be locked up")
                 .alias("amount")
                 .short_alias('a')
-                .value_parser(value_parser!(f64))
+                .allow_hyphen_values(true)
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
+              arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
             ])
         )
         .subcommand(
           Command::new("unbond")
             .about("As a controller, unbond an amount that has been bonded to you for staking.")
             .short_flag('u')
+            .args(signer_args("the controller"))
+            .mut_arg("key", |key| key.alias("controller").short_alias('c'))
             .args(&[
-              arg!(key: -k --key <CONTROLLER_KEY> "The 32-byte hexadecimal signing key of controller")
-                .alias("controller")
-                .short_alias('c')
-                .required(true),
-              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX) that will be unbonded")
+              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX, or with a unit) that will be unbonded")
                 .alias("amount")
                 .short_alias('a')
-                .value_parser(value_parser!(f64))
+                .allow_hyphen_values(true)
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
+              arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
             ])
         )
         .subcommand(
           Command::new("extra")
             .about("Take the origin account as a stash, bonding an extra amount for staking rewards")
             .short_flag('e')
+            .args(signer_args("the stash"))
+            .mut_arg("key", |key| key.alias("stash").short_alias('s'))
             .args(&[
-              arg!(key: -k --key <STASH_KEY> "The 32-byte hexadecimal signing key of stash")
-                .alias("stash")
-                .short_alias('s')
-                .required(true),
-              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX) that will be locked up (with no upper limit on this amount)")
+              arg!(value: -v --value <VALUE> "The amount of the stash's balance (in POLYX, or with a unit) that will be locked up (with no upper limit on this amount)")
                 .alias("amount")
                 .short_alias('a')
-                .value_parser(value_parser!(f64))
+                .allow_hyphen_values(true)
                 .required(true),
-              arg!(mainnet: --mainnet "If set, performs action on mainnet").required(false),
+              arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
             ])
         )
         .subcommand(
           Command::new("withdraw")
             .about("Taking the origin as a controller, withdraw unbonded tokens (if the unbonding period has ended)")
             .short_flag('w')
+            .args(signer_args("the controller"))
+        )
+    )
+
+    // Subcommand: tx
+    .subcommand(
+      Command::new("tx")
+        .about("Build, sign and submit transactions as separate steps, for signing offline")
+        .subcommand_required(true)
+        .subcommand(
+          Command::new("build")
+            .about("Build an unsigned transaction for an account to sign offline")
+            .subcommand_required(true)
+            .args(&[
+              arg!(from: --from <ADDRESS> "SS58-formatted public address of the account that will sign")
+                .required(true),
+              arg!(out: --out <FILE> "Also write the unsigned transaction to this file"),
+            ])
+            .subcommand(
+              Command::new("send")
+                .about("Send POLYX to another account")
+                .args(&[
+                  arg!(amount: -a --amount <AMOUNT> "Amount to transfer in POLYX, or with a unit, e.g. 1.5mPOLYX")
+                    .allow_hyphen_values(true)
+                    .required(true),
+                  arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
+                  arg!(destination: -d --destination <DESTINATION> "Public SS58 address of receiving account")
+                    .required(true),
+                ])
+            )
+            .subcommand(
+              Command::new("nominate")
+                .about("As a controller, nominate validator nodes")
+                .args(&[
+                  arg!(validators: -v --validators <VALIDATORS> "The validator nodes to nominate (up to 24)")
+                    .num_args(1..=24)
+                    .required(true),
+                ])
+            )
+            .subcommand(
+              Command::new("bond")
+                .about("As a stash, lock up some of its balance for staking")
+                .args(&[
+                  arg!(controller: -c --controller <CONTROLLER_ADDR> "The public address of the controller account")
+                    .required(true),
+                  arg!(value: -v --value <VALUE> "The amount (in POLYX, or with a unit) that will be locked up")
+                    .allow_hyphen_values(true)
+                    .required(true),
+                  arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
+                ])
+            )
+            .subcommand(
+              Command::new("unbond")
+                .about("As a controller, unbond an amount of stake")
+                .args(&[
+                  arg!(value: -v --value <VALUE> "The amount (in POLYX, or with a unit) that will be unbonded")
+                    .allow_hyphen_values(true)
+                    .required(true),
+                  arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
+                ])
+            )
+            .subcommand(
+              Command::new("extra")
+                .about("As a stash, bond an extra amount for staking")
+                .args(&[
+                  arg!(value: -v --value <VALUE> "The amount (in POLYX, or with a unit) that will be locked up")
+                    .allow_hyphen_values(true)
+                    .required(true),
+                  arg!(raw: --raw "If set, the amount is a whole number of μPOLYX"),
+                ])
+            )
+            .subcommand(
+              Command::new("withdraw")
+                .about("As a controller, withdraw unbonded tokens")
+            )
+        )
+        .subcommand(
+          Command::new("sign")
+            .about("Sign an unsigned transaction file without connecting to a node")
+            .args(signer_args("the signing account"))
+            .args(&[
+              arg!(payload: <PAYLOAD_FILE> "Unsigned transaction file written by tx build"),
+              arg!(out: --out <FILE> "Also write the hexadecimal signed extrinsic to this file"),
+            ])
+        )
+        .subcommand(
+          Command::new("submit")
+            .about("Submit a signed extrinsic and wait for it")
+            .args(&[
+              arg!(extrinsic: [EXTRINSIC] "Hexadecimal signed extrinsic, as printed by tx sign")
+                .required_unless_present("file"),
+              arg!(file: -f --file <FILE> "Read the hexadecimal signed extrinsic from this file")
+                .conflicts_with("extrinsic"),
+            ])
+        )
+    )
+
+    // Subcommand: decode
+    .subcommand(
+      Command::new("decode")
+        .about("Decode hexadecimal extrinsics and calls using the chain's metadata")
+        .subcommand_required(true)
+        .subcommand(
+          Command::new("extrinsic")
+            .about("Decode a signed or unsigned extrinsic, e.g. as printed by tx sign")
             .args(&[
-              arg!(key: -k --key <CONTROLLER_KEY> "The 32-byte hexadecimal signing key of the controller account").required(true),
-              arg!(mainnet: -m --mainnet "If set, performs the action on mainnet").required(false),
+              arg!(hex: <HEX> "Hexadecimal, length-prefixed extrinsic"),
+            ])
+        )
+        .subcommand(
+          Command::new("call")
+            .about("Decode a call, e.g. of a transaction payload or a multisig proposal")
+            .args(&[
+              arg!(hex: <HEX> "Hexadecimal call"),
             ])
         )
     )
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_builds_a_consistent_command() {
+    // Checks every subcommand for clashing flags, unknown argument IDs and the like, on a stack as
+    // large as the main thread's since debug builds of the command need more than a test thread's
+    std::thread::Builder::new()
+      .stack_size(8 << 20)
+      .spawn(|| command().debug_assert())
+      .unwrap()
+      .join()
+      .unwrap();
+  }
+}
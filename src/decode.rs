@@ -0,0 +1,201 @@
+use crate::{
+  amount::Balance,
+  chain::ChainContext,
+  extrinsic::Era,
+  metadata::{CallReport, Metadata},
+  network::Network,
+  output::{ErrorCode, Failure},
+};
+use anyhow::{Context, Result};
+use parity_scale_codec::{Compact, Decode};
+use polymesh_api::client::sp_core::hashing::blake2_256;
+use scale_value::{At, ValueDef};
+use serde::Serialize;
+use std::fmt;
+
+/// A decoded extrinsic, with its signer, nonce, era and tip if it is signed. The tip is serialized
+/// in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct ExtrinsicReport {
+  /// Transaction hash as a hexadecimal string with an `0x` prefix
+  pub hash: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signer: Option<String>,
+  /// Signature scheme, e.g. `sr25519`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scheme: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub nonce: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub era: Option<Era>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tip: Option<Balance>,
+  pub call: CallReport,
+  #[serde(skip)]
+  symbol: String,
+}
+
+impl fmt::Display for ExtrinsicReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "hash: {}", self.hash)?;
+    match (&self.signer, &self.scheme) {
+      (Some(signer), Some(scheme)) => writeln!(f, "signer: {signer} ({scheme})")?,
+      (Some(signer), None) => writeln!(f, "signer: {signer}")?,
+      _ => writeln!(f, "unsigned")?,
+    }
+    if let Some(nonce) = self.nonce {
+      writeln!(f, "nonce: {nonce}")?;
+    }
+    if let Some(era) = self.era {
+      writeln!(f, "era: {era}")?;
+    }
+    if let Some(tip) = self.tip {
+      writeln!(f, "tip: {}", tip.with_symbol(&self.symbol))?;
+    }
+    write!(f, "call: {}", self.call)
+  }
+}
+
+/// Decode a hexadecimal call using the metadata of the chain of `network`
+pub async fn call(hex: &str, network: &Network) -> Result<CallReport> {
+  let bytes = parse_hex(hex)?;
+  let (metadata, _) = metadata(network).await?;
+  metadata.decode_call(&bytes)
+}
+
+/// Decode a hexadecimal, length-prefixed extrinsic using the metadata of the chain of `network`
+pub async fn extrinsic(hex: &str, network: &Network) -> Result<ExtrinsicReport> {
+  let bytes = parse_hex(hex)?;
+  let (metadata, symbol) = metadata(network).await?;
+  decode_extrinsic(&metadata, &bytes, &symbol)
+}
+
+/// The latest metadata of the chain of `network`, rendering balances in its token symbol, along
+/// with that symbol
+async fn metadata(network: &Network) -> Result<(Metadata, String)> {
+  let chain = ChainContext::connect(network).await?;
+  let mut metadata = Metadata::fetch(&chain, None).await?;
+  metadata.render_balances(&chain.symbol);
+  Ok((metadata, chain.symbol))
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+  let hex = hex.trim();
+  hex::decode(hex.strip_prefix("0x").unwrap_or(hex)).map_err(|_| {
+    let message = format!("{hex} is not a hexadecimal string");
+    Failure::new(ErrorCode::InvalidInput, message).into()
+  })
+}
+
+/// Decode a SCALE-encoded, length-prefixed extrinsic of version 4
+fn decode_extrinsic(metadata: &Metadata, bytes: &[u8], symbol: &str) -> Result<ExtrinsicReport> {
+  let malformed = |reason: String| Failure::new(ErrorCode::InvalidInput, reason);
+  let mut input = bytes;
+  let len = Compact::<u32>::decode(&mut input)
+    .map_err(|_| malformed(String::from("the extrinsic has no length prefix")))?;
+  if len.0 as usize != input.len() {
+    let reason = format!(
+      "the extrinsic's length prefix is {} but {} bytes follow it",
+      len.0,
+      input.len()
+    );
+    return Err(malformed(reason).into());
+  }
+  let (&version, rest) = input
+    .split_first()
+    .ok_or_else(|| malformed(String::from("the extrinsic is empty")))?;
+  input = rest;
+  if version & 0x7f != 4 {
+    let reason = format!("unsupported extrinsic version {}", version & 0x7f);
+    return Err(malformed(reason).into());
+  }
+  let (mut signer, mut scheme, mut nonce, mut era, mut tip) = (None, None, None, None, None);
+  // Signed extrinsics have the high bit of their version set
+  if version & 0x80 != 0 {
+    let param = |name: &str| {
+      metadata
+        .extrinsic_param(name)
+        .with_context(|| format!("the runtime's extrinsics have no {name} type"))
+    };
+    let address = metadata.decode_from(&mut input, param("Address")?)?;
+    // A `MultiAddress::Id`, or else a bare account ID
+    let account = address.at(0).unwrap_or(&address);
+    signer = Some(match account.as_str() {
+      Some(account) => String::from(account),
+      None => account.to_string(),
+    });
+    let signature = metadata.decode_from(&mut input, param("Signature")?)?;
+    if let ValueDef::Variant(variant) = &signature.value {
+      scheme = Some(variant.name.to_lowercase());
+    }
+    for ext in metadata.signed_extensions() {
+      let start = input;
+      metadata
+        .decode_from(&mut input, ext.ty)
+        .with_context(|| format!("malformed {} data", ext.identifier))?;
+      let mut data = &start[..start.len() - input.len()];
+      match ext.identifier.as_str() {
+        "CheckMortality" | "CheckEra" => era = Some(Era::decode(data)?),
+        "CheckNonce" => nonce = Some(Compact::<u32>::decode(&mut data)?.0),
+        "ChargeTransactionPayment" => {
+          tip = Some(Balance::from_micro(Compact::<u128>::decode(&mut data)?.0))
+        }
+        _ => {}
+      }
+    }
+  }
+  Ok(ExtrinsicReport {
+    hash: String::from("0x") + &hex::encode(blake2_256(bytes)),
+    signer,
+    scheme,
+    nonce,
+    era,
+    tip,
+    call: metadata.decode_call(input)?,
+    symbol: String::from(symbol),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    offline::{self, OfflineCall},
+    util::{self, SignerSource, SignerSpec},
+  };
+  use polymesh_api::client::{sp_core::crypto::Ss58Codec, Signer};
+
+  #[test]
+  fn it_rejects_malformed_hex() {
+    assert!(parse_hex("0x0g").is_err());
+    assert_eq!(parse_hex(" 0x0a0b\n").unwrap(), [0x0a, 0x0b]);
+  }
+
+  #[tokio::test]
+  async fn it_decodes_signed_transfers() {
+    let network = Network::testnet();
+    let key = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let spec = SignerSpec::from(SignerSource::PrivateKey(String::from(key)));
+    let signer = util::signer(&spec).unwrap().account().to_ss58check();
+    let transfer = OfflineCall::Transfer {
+      dest: String::from("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"),
+      amount: "1.5".parse().unwrap(),
+    };
+    let payload = offline::build(&transfer, &signer, &network, &Default::default())
+      .await
+      .unwrap();
+    let signed = offline::sign(&payload, &spec).await.unwrap();
+
+    let report = extrinsic(&signed.extrinsic, &network).await.unwrap();
+    assert_eq!(report.hash, signed.hash);
+    assert_eq!(report.scheme.as_deref(), Some("sr25519"));
+    assert_eq!(report.nonce, Some(payload.nonce));
+    assert_eq!(report.era, Some(Era::Immortal));
+    assert_eq!(report.call.pallet, "Balances");
+    assert!(report.call.to_string().contains("1.5 POLYX"));
+
+    let decoded = call(&payload.call, &network).await.unwrap();
+    assert_eq!(decoded.to_string(), report.call.to_string());
+    assert!(extrinsic(&payload.call, &network).await.is_err());
+  }
+}
@@ -0,0 +1,469 @@
+use crate::{
+  amount::Balance,
+  chain::ChainContext,
+  metadata::Metadata,
+  output::{ErrorCode, Failure},
+};
+use anyhow::{bail, Context, Result};
+use jsonrpsee_core::rpc_params;
+use parity_scale_codec::{Compact, Encode};
+use polymesh_api::{
+  client::{
+    sp_core::{crypto::Ss58Codec, hashing::blake2_256},
+    AccountId, BlockHash, Signer,
+  },
+  WrappedCall,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of signed extrinsics, with the high bit set to mark them as signed
+const SIGNED_EXTRINSIC_VERSION: u8 = 0x80 | 4;
+
+/// Version of the format of unsigned transaction files
+pub const PAYLOAD_VERSION: u32 = 1;
+
+/// The runtime versions a transaction commits to, as returned by `state_getRuntimeVersion`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeVersion {
+  spec_version: u32,
+  transaction_version: u32,
+}
+
+/// The blocks a transaction is valid in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Era {
+  /// Valid in any block
+  Immortal,
+  /// Valid for `period` blocks from the first block whose number is `phase` modulo `period`
+  Mortal { period: u64, phase: u64 },
+}
+
+impl Era {
+  /// An era of at least `blocks` blocks, rounded up to a power of two between 4 and 65536, that
+  /// starts at block `current`
+  pub fn mortal(blocks: u64, current: u64) -> Self {
+    let period = blocks
+      .checked_next_power_of_two()
+      .unwrap_or(1 << 16)
+      .clamp(4, 1 << 16);
+    let quantize_factor = (period >> 12).max(1);
+    let phase = current % period / quantize_factor * quantize_factor;
+    Self::Mortal { period, phase }
+  }
+
+  /// The first block of the era that is at or before block `current`
+  pub fn birth(&self, current: u64) -> u64 {
+    match *self {
+      Self::Immortal => 0,
+      Self::Mortal { period, phase } => (current.max(phase) - phase) / period * period + phase,
+    }
+  }
+
+  /// Decode an era from the extra data of a transaction
+  pub fn decode(bytes: &[u8]) -> Result<Self> {
+    match *bytes {
+      [0] => Ok(Self::Immortal),
+      [low, high] => {
+        let encoded = u64::from(u16::from_le_bytes([low, high]));
+        let period = 2 << (encoded % (1 << 4));
+        let quantize_factor = (period >> 12).max(1);
+        let phase = (encoded >> 4) * quantize_factor;
+        if period < 4 || phase >= period {
+          bail!("invalid era 0x{}", hex::encode(bytes));
+        }
+        Ok(Self::Mortal { period, phase })
+      }
+      _ => bail!("invalid era 0x{}", hex::encode(bytes)),
+    }
+  }
+
+  fn encode_to(&self, out: &mut Vec<u8>) {
+    match *self {
+      Self::Immortal => out.push(0),
+      Self::Mortal { period, phase } => {
+        let quantize_factor = (period >> 12).max(1);
+        let low = (period.trailing_zeros().saturating_sub(1)).clamp(1, 15) as u16;
+        let high = ((phase / quantize_factor) << 4) as u16;
+        (low | high).encode_to(out);
+      }
+    }
+  }
+}
+
+impl fmt::Display for Era {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Immortal => write!(f, "immortal"),
+      Self::Mortal { period, phase } => write!(f, "{period} blocks (phase {phase})"),
+    }
+  }
+}
+
+/// Everything that goes into signing a transaction, short of the signature
+#[derive(Clone, Debug)]
+pub struct UnsignedTx {
+  /// SCALE-encoded call
+  pub call: Vec<u8>,
+  pub signer: AccountId,
+  pub nonce: u32,
+  /// Tip for the block author in μPOLYX
+  pub tip: u128,
+  pub spec_version: u32,
+  pub tx_version: u32,
+  pub genesis_hash: BlockHash,
+  pub era: Era,
+  /// Hash of the block the era starts at, which is the genesis block for immortal transactions
+  pub checkpoint: BlockHash,
+  /// Whether the signer is encoded as a `MultiAddress` rather than an account ID
+  pub multi_address: bool,
+  /// Identifiers of the signed extensions the runtime expects, in order
+  pub extensions: Vec<String>,
+}
+
+impl UnsignedTx {
+  /// Gather what it takes to sign `call` as `signer`, using the signer's on-chain nonce
+  pub async fn new(chain: &ChainContext, call: &WrappedCall, signer: AccountId) -> Result<Self> {
+    let metadata = Metadata::fetch(chain, None).await?;
+    let client = chain.api.client();
+    let version: RuntimeVersion = client
+      .request("state_getRuntimeVersion", rpc_params!())
+      .await?;
+    let nonce = chain.query().system().account(signer).await?.nonce;
+    let mut extensions = Vec::new();
+    for ext in metadata.signed_extensions() {
+      if ext.has_data && !is_supported(&ext.identifier) {
+        bail!("unsupported signed extension {}", ext.identifier);
+      }
+      extensions.push(ext.identifier);
+    }
+    let genesis_hash = BlockHash::from(chain.genesis_hash.to_fixed_bytes());
+    Ok(Self {
+      call: call.runtime_call().encode(),
+      signer,
+      nonce,
+      tip: 0,
+      spec_version: version.spec_version,
+      tx_version: version.transaction_version,
+      genesis_hash,
+      era: Era::Immortal,
+      checkpoint: genesis_hash,
+      multi_address: metadata.multi_address(),
+      extensions,
+    })
+  }
+
+  /// Make the transaction valid for at least `blocks` blocks from the latest finalized block
+  pub async fn set_mortality(&mut self, chain: &ChainContext, blocks: u64) -> Result<()> {
+    let client = chain.api.client();
+    let finalized: BlockHash = client
+      .request("chain_getFinalizedHead", rpc_params!())
+      .await?;
+    let current = client
+      .get_block_header(Some(finalized))
+      .await?
+      .context("the finalized block has no header")?
+      .number;
+    let era = Era::mortal(blocks, u64::from(current));
+    let birth = u32::try_from(era.birth(u64::from(current)))?;
+    self.checkpoint = client
+      .get_block_hash(birth)
+      .await?
+      .with_context(|| format!("block #{birth} not found"))?;
+    self.era = era;
+    Ok(())
+  }
+
+  /// Data the signed extensions add to the extrinsic itself
+  fn extra(&self) -> Vec<u8> {
+    let mut extra = Vec::new();
+    for ext in &self.extensions {
+      match ext.as_str() {
+        "CheckMortality" | "CheckEra" => self.era.encode_to(&mut extra),
+        "CheckNonce" => Compact(self.nonce).encode_to(&mut extra),
+        "ChargeTransactionPayment" => Compact(self.tip).encode_to(&mut extra),
+        _ => {}
+      }
+    }
+    extra
+  }
+
+  /// Data the signed extensions add to the signed payload only
+  fn additional_signed(&self) -> Vec<u8> {
+    let mut additional = Vec::new();
+    for ext in &self.extensions {
+      match ext.as_str() {
+        "CheckSpecVersion" => self.spec_version.encode_to(&mut additional),
+        "CheckTxVersion" => self.tx_version.encode_to(&mut additional),
+        "CheckGenesis" => self.genesis_hash.encode_to(&mut additional),
+        "CheckMortality" | "CheckEra" => self.checkpoint.encode_to(&mut additional),
+        _ => {}
+      }
+    }
+    additional
+  }
+
+  /// The bytes the signer signs, which are hashed if longer than 256 bytes
+  pub fn signing_payload(&self) -> Vec<u8> {
+    let payload = [
+      self.call.as_slice(),
+      &self.extra(),
+      &self.additional_signed(),
+    ]
+    .concat();
+    if payload.len() > 256 {
+      blake2_256(&payload).to_vec()
+    } else {
+      payload
+    }
+  }
+
+  /// Sign the transaction, returning the SCALE-encoded extrinsic ready to be submitted
+  pub async fn sign(&self, signer: &impl Signer) -> Result<Vec<u8>> {
+    if signer.account() != self.signer {
+      bail!("the transaction must be signed by its signer");
+    }
+    let signature = signer.sign(&self.signing_payload()).await?;
+    let mut body = vec![SIGNED_EXTRINSIC_VERSION];
+    if self.multi_address {
+      // `MultiAddress::Id`
+      body.push(0);
+    }
+    self.signer.encode_to(&mut body);
+    signature.encode_to(&mut body);
+    body.extend(self.extra());
+    body.extend_from_slice(&self.call);
+    Ok(body.encode())
+  }
+}
+
+/// An unsigned transaction as written to a file by `tx build`, for `tx sign` to sign offline. Hashes
+/// and the call are hexadecimal strings with an `0x` prefix, and the tip is in μPOLYX.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TxPayload {
+  /// Version of the file format, which is [`PAYLOAD_VERSION`]
+  pub version: u32,
+  pub chain: String,
+  pub genesis_hash: String,
+  pub spec_version: u32,
+  pub tx_version: u32,
+  /// SS58 address of the account that must sign the transaction
+  pub signer: String,
+  pub nonce: u32,
+  pub era: Era,
+  /// Hash of the block the era starts at
+  pub checkpoint: String,
+  pub tip: Balance,
+  /// The call as `tx build` decoded it for review. Nothing ties it to `call`, so a tampered or
+  /// stale payload may describe another call than the one signed.
+  pub description: String,
+  /// SCALE-encoded call
+  pub call: String,
+  pub multi_address: bool,
+  pub signed_extensions: Vec<String>,
+}
+
+impl TxPayload {
+  pub fn new(tx: &UnsignedTx, chain: &ChainContext, description: String) -> Self {
+    Self {
+      version: PAYLOAD_VERSION,
+      chain: chain.name.clone(),
+      genesis_hash: hex_string(tx.genesis_hash.as_ref()),
+      spec_version: tx.spec_version,
+      tx_version: tx.tx_version,
+      signer: chain.ss58check(&tx.signer),
+      nonce: tx.nonce,
+      era: tx.era,
+      checkpoint: hex_string(tx.checkpoint.as_ref()),
+      tip: Balance::from_micro(tx.tip),
+      description,
+      call: hex_string(&tx.call),
+      multi_address: tx.multi_address,
+      signed_extensions: tx.extensions.clone(),
+    }
+  }
+
+  /// Blake2-256 hash of the call, shown by both `tx build` and `tx sign` so that the call signed
+  /// can be checked against the one built
+  pub fn call_hash(&self) -> Result<String> {
+    let call = decode_hex(&self.call).context("invalid call")?;
+    Ok(hex_string(&blake2_256(&call)))
+  }
+
+  /// The transaction to sign, if the payload is of a supported version and well-formed
+  pub fn unsigned(&self) -> Result<UnsignedTx> {
+    if self.version != PAYLOAD_VERSION {
+      let message = format!(
+        "unsupported payload version {}, expected {PAYLOAD_VERSION}",
+        self.version
+      );
+      return Err(Failure::new(ErrorCode::InvalidInput, message).into());
+    }
+    Ok(UnsignedTx {
+      call: decode_hex(&self.call).context("invalid call")?,
+      signer: AccountId::from_ss58check(&self.signer).context("invalid signer")?,
+      nonce: self.nonce,
+      tip: self.tip.micro(),
+      spec_version: self.spec_version,
+      tx_version: self.tx_version,
+      genesis_hash: decode_hash(&self.genesis_hash).context("invalid genesis hash")?,
+      era: self.era,
+      checkpoint: decode_hash(&self.checkpoint).context("invalid checkpoint")?,
+      multi_address: self.multi_address,
+      extensions: self.signed_extensions.clone(),
+    })
+  }
+}
+
+impl fmt::Display for TxPayload {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "chain: {} ({})", self.chain, self.genesis_hash)?;
+    writeln!(f, "signer: {}", self.signer)?;
+    writeln!(f, "call: {}", self.description)?;
+    let call_hash = self
+      .call_hash()
+      .unwrap_or_else(|_| String::from("invalid call"));
+    writeln!(f, "call hash: {call_hash}")?;
+    writeln!(f, "nonce: {}", self.nonce)?;
+    writeln!(f, "era: {}", self.era)?;
+    writeln!(f, "tip: {}", self.tip)?;
+    write!(
+      f,
+      "runtime: spec version {}, transaction version {}",
+      self.spec_version, self.tx_version
+    )
+  }
+}
+
+/// Whether the data of a signed extension can be encoded
+fn is_supported(identifier: &str) -> bool {
+  matches!(
+    identifier,
+    "CheckSpecVersion"
+      | "CheckTxVersion"
+      | "CheckGenesis"
+      | "CheckMortality"
+      | "CheckEra"
+      | "CheckNonce"
+      | "ChargeTransactionPayment"
+  )
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+  String::from("0x") + &hex::encode(bytes)
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+  Ok(hex::decode(s.strip_prefix("0x").unwrap_or(s))?)
+}
+
+fn decode_hash(s: &str) -> Result<BlockHash> {
+  let bytes = <[u8; 32]>::try_from(decode_hex(s)?.as_slice())?;
+  Ok(BlockHash::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::util::{signer, SignerSource, SignerSpec};
+
+  fn unsigned(signer: AccountId) -> UnsignedTx {
+    UnsignedTx {
+      call: vec![0x05, 0x00],
+      signer,
+      nonce: 7,
+      tip: 0,
+      spec_version: 5_002_000,
+      tx_version: 3,
+      genesis_hash: BlockHash::from([0xab; 32]),
+      era: Era::Immortal,
+      checkpoint: BlockHash::from([0xab; 32]),
+      multi_address: true,
+      extensions: [
+        "CheckSpecVersion",
+        "CheckTxVersion",
+        "CheckGenesis",
+        "CheckMortality",
+        "CheckNonce",
+        "CheckWeight",
+        "ChargeTransactionPayment",
+      ]
+      .map(String::from)
+      .to_vec(),
+    }
+  }
+
+  #[test]
+  fn it_decodes_eras() {
+    assert_eq!(Era::decode(&[0]).unwrap(), Era::Immortal);
+    let era = Era::Mortal {
+      period: 64,
+      phase: 42,
+    };
+    let mut encoded = Vec::new();
+    era.encode_to(&mut encoded);
+    assert_eq!(encoded, [0xa5, 0x02]);
+    assert_eq!(Era::decode(&encoded).unwrap(), era);
+    assert!(Era::decode(&[1, 2, 3]).is_err());
+  }
+
+  #[test]
+  fn it_rounds_mortal_eras_to_their_period() {
+    let era = Era::mortal(50, 1_000_042);
+    assert_eq!(
+      era,
+      Era::Mortal {
+        period: 64,
+        phase: 1_000_042 % 64
+      }
+    );
+    assert_eq!(era.birth(1_000_042), 1_000_042);
+    assert_eq!(era.birth(1_000_050), 1_000_042);
+    assert_eq!(
+      Era::mortal(1, 7),
+      Era::Mortal {
+        period: 4,
+        phase: 3
+      }
+    );
+    // Long eras have their phase quantized
+    let era = Era::mortal(100_000, 1_000_042);
+    assert_eq!(
+      era,
+      Era::Mortal {
+        period: 65_536,
+        phase: 16_992
+      }
+    );
+    assert_eq!(era.birth(1_000_042), 1_000_032);
+  }
+
+  #[test]
+  fn it_encodes_the_signing_payload() {
+    let tx = unsigned(AccountId::from([1; 32]));
+    let mut expected = vec![0x05, 0x00, 0x00, 7 << 2, 0x00];
+    expected.extend(5_002_000u32.to_le_bytes());
+    expected.extend(3u32.to_le_bytes());
+    expected.extend([0xab; 64]);
+    assert_eq!(tx.signing_payload(), expected);
+  }
+
+  #[tokio::test]
+  async fn it_signs_extrinsics() {
+    let key = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let signer = signer(&SignerSpec::from(SignerSource::PrivateKey(String::from(
+      key,
+    ))))
+    .unwrap();
+    let tx = unsigned(signer.account());
+    let extrinsic = tx.sign(&signer).await.unwrap();
+    // Length prefix, version, address, sr25519 signature, extra and call
+    let len = 1 + 33 + 65 + 3 + 2;
+    assert_eq!(extrinsic.len(), 2 + len);
+    assert_eq!(extrinsic[2], SIGNED_EXTRINSIC_VERSION);
+    assert_eq!(extrinsic[4..36], signer.account().encode());
+    assert_eq!(&extrinsic[extrinsic.len() - 2..], &[0x05, 0x00]);
+  }
+}
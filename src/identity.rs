@@ -0,0 +1,152 @@
+use crate::{
+  block::{AtBlock, BlockId},
+  chain::ChainContext,
+  network::Network,
+  permissions::PermissionSet,
+};
+use anyhow::{bail, Result};
+use polymesh_api::{
+  client::{sp_core::crypto::Ss58Codec, AccountId, IdentityId},
+  types::polymesh_primitives::secondary_key::{KeyRecord, Permissions},
+};
+use serde::Serialize;
+use std::fmt;
+
+/// The role an account's key plays for its identity
+pub enum KeyKind {
+  Primary,
+  Secondary(Permissions),
+  /// Signer of a MultiSig account, which itself holds a key of the identity
+  MultiSigSigner(AccountId),
+}
+
+/// The identity (DID) an account is linked to, and the kind of key it holds for that identity
+pub struct KeyInfo {
+  pub did: IdentityId,
+  pub kind: KeyKind,
+}
+
+/// Render a DID as a `0x`-prefixed hexadecimal string
+pub fn did_to_hex(did: &IdentityId) -> String {
+  String::from("0x") + &hex::encode(did.0)
+}
+
+/// The kind of key an account holds, as reported
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeyReport {
+  Primary,
+  Secondary { permissions: PermissionSet },
+  MultisigSigner { multisig: String },
+}
+
+/// The identity an account is linked to
+#[derive(Serialize, Clone, Debug)]
+pub struct IdentityReport {
+  pub address: String,
+  pub did: String,
+  pub key: KeyReport,
+}
+
+impl fmt::Display for IdentityReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{}", self.did)?;
+    match &self.key {
+      KeyReport::Primary => write!(f, "key: primary"),
+      KeyReport::Secondary { permissions } => write!(f, "key: secondary\n{permissions}"),
+      KeyReport::MultisigSigner { multisig } => write!(f, "key: multisig signer of {multisig}"),
+    }
+  }
+}
+
+impl KeyInfo {
+  /// Report the DID and key of `account`, formatting addresses for `chain`
+  pub fn report(&self, chain: &ChainContext, account: &AccountId) -> IdentityReport {
+    let key = match &self.kind {
+      KeyKind::Primary => KeyReport::Primary,
+      KeyKind::Secondary(permissions) => KeyReport::Secondary {
+        permissions: PermissionSet::from_permissions(permissions, self.did),
+      },
+      KeyKind::MultiSigSigner(multisig) => KeyReport::MultisigSigner {
+        multisig: chain.ss58check(multisig),
+      },
+    };
+    IdentityReport {
+      address: chain.ss58check(account),
+      did: did_to_hex(&self.did),
+      key,
+    }
+  }
+}
+
+/// Look up the identity an account belongs to, along with the kind of key it holds
+pub async fn key_info(chain: &ChainContext, account: AccountId) -> Result<KeyInfo> {
+  let identity_query = chain.query().identity();
+  let info = match identity_query.key_records(account).await? {
+    Some(KeyRecord::PrimaryKey(did)) => KeyInfo {
+      did,
+      kind: KeyKind::Primary,
+    },
+    Some(KeyRecord::SecondaryKey(did, permissions)) => KeyInfo {
+      did,
+      kind: KeyKind::Secondary(permissions),
+    },
+    Some(KeyRecord::MultiSigSignerKey(multisig)) => {
+      // A MultiSig signer has no DID of its own; it acts for the MultiSig's identity
+      let did = match identity_query.key_records(multisig).await? {
+        Some(KeyRecord::PrimaryKey(did)) | Some(KeyRecord::SecondaryKey(did, _)) => did,
+        _ => bail!(
+          "multisig {} doesn't have an identity",
+          chain.ss58check(&multisig)
+        ),
+      };
+      KeyInfo {
+        did,
+        kind: KeyKind::MultiSigSigner(multisig),
+      }
+    }
+    None => bail!("{} doesn't have an identity", chain.ss58check(&account)),
+  };
+  Ok(info)
+}
+
+/// Get the DID associated with an SS58-formatted address, at block `at` if set
+pub async fn did(
+  addr: &str,
+  network: &Network,
+  at: Option<BlockId>,
+) -> Result<AtBlock<IdentityReport>> {
+  let account = AccountId::from_string(addr)?;
+  let (chain, block) = ChainContext::connect_at(network, at).await?;
+  let info = key_info(&chain, account).await?;
+  let report = info.report(&chain, &account);
+  Ok(AtBlock { report, block })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_renders_did_as_hex() {
+    let did = IdentityId([0xab; 32]);
+    let expected = String::from("0x") + &"ab".repeat(32);
+    assert_eq!(did_to_hex(&did), expected);
+  }
+
+  #[test]
+  fn it_describes_secondary_keys() {
+    let report = IdentityReport {
+      address: String::new(),
+      did: did_to_hex(&IdentityId([0xab; 32])),
+      key: KeyReport::Secondary {
+        permissions: PermissionSet::default(),
+      },
+    };
+    let expected = format!(
+      "0x{}\nkey: secondary\nassets: whole\nportfolios: whole\nextrinsics: whole",
+      "ab".repeat(32)
+    );
+    assert_eq!(report.to_string(), expected);
+  }
+}
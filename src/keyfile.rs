@@ -0,0 +1,243 @@
+use crate::{
+  output::{ErrorCode, Failure},
+  util::{self, AnySigner, SignerSource},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crypto_secretbox::{
+  aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+  Key, Nonce, XSalsa20Poly1305,
+};
+use polymesh_api::client::{
+  sp_core::{crypto::Ss58Codec, Pair},
+  AccountId, Signer,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::SystemTime};
+
+// PKCS#8 framing polkadot{.js} wraps around the secret and public key of an sr25519 pair
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_KEY_LEN: usize = 64;
+const PUBLIC_KEY_LEN: usize = 32;
+
+// polkadot{.js} defaults for scrypt (N = 2^15, p = 1, r = 8)
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_P: u32 = 1;
+const SCRYPT_R: u32 = 8;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const ENCODING_VERSION: &str = "3";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Encoding {
+  pub content: Vec<String>,
+  #[serde(rename = "type")]
+  pub kind: Vec<String>,
+  pub version: String,
+}
+
+/// A polkadot{.js} JSON key file, as exported by the browser extension or `@polkadot/keyring`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyFile {
+  /// Base64-encoded scrypt parameters, nonce and xsalsa20-poly1305 ciphertext of the key pair
+  pub encoded: String,
+  pub encoding: Encoding,
+  pub address: String,
+  #[serde(default)]
+  pub meta: serde_json::Value,
+}
+
+impl fmt::Display for KeyFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+    write!(f, "{json}")
+  }
+}
+
+impl KeyFile {
+  /// The account name stored in the file's metadata, if any
+  pub fn name(&self) -> Option<&str> {
+    self.meta.get("name").and_then(|name| name.as_str())
+  }
+}
+
+fn scrypt_key(password: &str, salt: &[u8], log_n: u8, p: u32, r: u32) -> Result<Key> {
+  let params = scrypt::Params::new(log_n, r, p, 32).map_err(|e| anyhow!("{e}"))?;
+  let mut key = Key::default();
+  scrypt::scrypt(password.as_bytes(), salt, &params, &mut key).map_err(|e| anyhow!("{e}"))?;
+  Ok(key)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+  u32::from_le_bytes(bytes.try_into().expect("slice of 4 bytes"))
+}
+
+/// Decrypt a key file with its password, yielding the account's 64-byte sr25519 secret key
+pub fn decrypt(file: &KeyFile, password: &str) -> Result<SignerSource> {
+  let Encoding {
+    content,
+    kind,
+    version,
+  } = &file.encoding;
+  if version != ENCODING_VERSION
+    || kind
+      .iter()
+      .map(String::as_str)
+      .ne(["scrypt", "xsalsa20-poly1305"])
+  {
+    bail!("unsupported key file encoding {kind:?} (version {version})");
+  }
+  if content.get(1).map(String::as_str) != Some("sr25519") {
+    bail!("only sr25519 key files are supported, got {content:?}");
+  }
+
+  let encoded = BASE64.decode(&file.encoded)?;
+  let header_len = SALT_LEN + 12 + NONCE_LEN;
+  if encoded.len() <= header_len {
+    bail!("encoded key pair is too short");
+  }
+  let (salt, rest) = encoded.split_at(SALT_LEN);
+  let n = read_u32_le(&rest[0..4]);
+  let p = read_u32_le(&rest[4..8]);
+  let r = read_u32_le(&rest[8..12]);
+  if !n.is_power_of_two() {
+    bail!("scrypt N must be a power of two, got {n}");
+  }
+  let key = scrypt_key(password, salt, n.trailing_zeros() as u8, p, r)?;
+  let (nonce, ciphertext) = rest[12..].split_at(NONCE_LEN);
+  let pkcs8 = XSalsa20Poly1305::new(&key)
+    .decrypt(Nonce::from_slice(nonce), ciphertext)
+    .map_err(|_| Failure::new(ErrorCode::Keystore, "incorrect password for key file"))?;
+
+  let secret_start = PKCS8_HEADER.len();
+  let divider_start = secret_start + SECRET_KEY_LEN;
+  if pkcs8.len() != divider_start + PKCS8_DIVIDER.len() + PUBLIC_KEY_LEN
+    || pkcs8[..secret_start] != PKCS8_HEADER
+    || pkcs8[divider_start..divider_start + PKCS8_DIVIDER.len()] != PKCS8_DIVIDER
+  {
+    bail!("decrypted key pair is not valid PKCS#8");
+  }
+  // polkadot{.js} stores the secret key in its ed25519-compatible form
+  let secret = schnorrkel::SecretKey::from_ed25519_bytes(&pkcs8[secret_start..divider_start])
+    .map_err(|e| anyhow!("invalid sr25519 secret key: {e}"))?;
+  let secret = SignerSource::PrivateKey(hex::encode(secret.to_bytes()));
+
+  let account = util::signer(&secret.clone().into())?.account();
+  if AccountId::from_ss58check(&file.address)? != account {
+    bail!(
+      "key file is for {}, but its key pair belongs to {}",
+      file.address,
+      account.to_ss58check()
+    );
+  }
+  Ok(secret)
+}
+
+/// Encrypt a signing account into a key file that polkadot{.js} can import
+pub fn encrypt(secret: &SignerSource, name: &str, password: &str) -> Result<KeyFile> {
+  let signer = match util::signer(&secret.clone().into())? {
+    AnySigner::Sr25519(signer) => signer,
+    signer => bail!("key files only hold sr25519 keys, not {}", signer.scheme()),
+  };
+  let secret = schnorrkel::SecretKey::from_bytes(&signer.pair.to_raw_vec())
+    .map_err(|e| anyhow!("invalid sr25519 secret key: {e}"))?;
+  let mut pkcs8 = PKCS8_HEADER.to_vec();
+  pkcs8.extend_from_slice(&secret.to_ed25519_bytes());
+  pkcs8.extend_from_slice(&PKCS8_DIVIDER);
+  pkcs8.extend_from_slice(&signer.pair.public().0);
+
+  let mut salt = [0u8; SALT_LEN];
+  OsRng.fill_bytes(&mut salt);
+  let mut nonce = [0u8; NONCE_LEN];
+  OsRng.fill_bytes(&mut nonce);
+  let key = scrypt_key(password, &salt, SCRYPT_LOG_N, SCRYPT_P, SCRYPT_R)?;
+  let ciphertext = XSalsa20Poly1305::new(&key)
+    .encrypt(Nonce::from_slice(&nonce), &pkcs8[..])
+    .map_err(|_| anyhow!("failed to encrypt key pair"))?;
+
+  let mut encoded = salt.to_vec();
+  encoded.extend_from_slice(&(1u32 << SCRYPT_LOG_N).to_le_bytes());
+  encoded.extend_from_slice(&SCRYPT_P.to_le_bytes());
+  encoded.extend_from_slice(&SCRYPT_R.to_le_bytes());
+  encoded.extend_from_slice(&nonce);
+  encoded.extend_from_slice(&ciphertext);
+
+  let when_created = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .expect("logic error in getting Unix time")
+    .as_millis() as u64;
+  Ok(KeyFile {
+    encoded: BASE64.encode(encoded),
+    encoding: Encoding {
+      content: vec![String::from("pkcs8"), String::from("sr25519")],
+      kind: vec![String::from("scrypt"), String::from("xsalsa20-poly1305")],
+      version: String::from(ENCODING_VERSION),
+    },
+    address: signer.account().to_ss58check(),
+    meta: serde_json::json!({ "name": name, "whenCreated": when_created }),
+  })
+}
+
+/// Read a polkadot{.js} key file, prompting for its password
+pub fn read(path: &str) -> Result<(KeyFile, SignerSource)> {
+  let contents = std::fs::read_to_string(path).with_context(|| format!("could not read {path}"))?;
+  let file: KeyFile = serde_json::from_str(&contents).context("malformed key file")?;
+  let password = rpassword::prompt_password("Key file password: ")?;
+  let secret = decrypt(&file, &password)?;
+  Ok((file, secret))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // polkadot{.js} v3 key file (scrypt + xsalsa20-poly1305) for the private key
+  // 6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e
+  const FIXTURE: &str = include_str!("../tests/fixtures/polkadotjs_sr25519.json");
+  const FIXTURE_PASSWORD: &str = "polymesh-cli";
+
+  #[test]
+  fn it_decrypts_a_polkadotjs_key_file() {
+    let file: KeyFile = serde_json::from_str(FIXTURE).unwrap();
+    let secret = decrypt(&file, FIXTURE_PASSWORD).unwrap();
+    let signer = util::signer(&secret.into()).unwrap();
+    assert_eq!(
+      signer.account().to_ss58check(),
+      "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"
+    );
+  }
+
+  #[test]
+  fn it_derives_from_an_imported_key() {
+    let file: KeyFile = serde_json::from_str(FIXTURE).unwrap();
+    let derive = |source: SignerSource| util::SignerSpec {
+      derivation: Some(String::from("//0")),
+      ..util::SignerSpec::from(source)
+    };
+    let imported = derive(decrypt(&file, FIXTURE_PASSWORD).unwrap());
+    let seed = derive(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
+    let account = util::signer(&imported).unwrap().account();
+    assert_eq!(account, util::signer(&seed).unwrap().account());
+    assert_ne!(account.to_ss58check(), file.address);
+  }
+
+  #[test]
+  fn it_rejects_the_wrong_password() {
+    let file: KeyFile = serde_json::from_str(FIXTURE).unwrap();
+    assert!(decrypt(&file, "not the password").is_err());
+  }
+
+  #[test]
+  fn it_round_trips_a_key_file() {
+    let file: KeyFile = serde_json::from_str(FIXTURE).unwrap();
+    let secret = decrypt(&file, FIXTURE_PASSWORD).unwrap();
+    let exported = encrypt(&secret, "exported", "another password").unwrap();
+    assert_eq!(exported.address, file.address);
+    assert_eq!(exported.name(), Some("exported"));
+    let reimported = decrypt(&exported, "another password").unwrap();
+    assert_eq!(reimported, secret);
+  }
+}
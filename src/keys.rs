@@ -0,0 +1,512 @@
+use crate::{
+  address,
+  keyfile::{self, KeyFile},
+  network::Network,
+  output::{ErrorCode, Failure},
+  util::{self, AnySigner, Scheme, SignerSource, SignerSpec},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+  aead::{rand_core::RngCore, Aead, OsRng},
+  AeadCore, Key, KeyInit, XChaCha20Poly1305, XNonce,
+};
+use polymesh_api::client::{
+  sp_core::{crypto::Ss58Codec, ecdsa, ed25519, sr25519, Pair},
+  PairSigner, Signer,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, io::Write, path::PathBuf};
+
+/// If set, overrides the location of the keystore file
+const KEYSTORE_ENV: &str = "POLYMESH_CLI_KEYSTORE";
+/// If set, used as the keystore passphrase instead of prompting for it
+const PASSPHRASE_ENV: &str = "POLYMESH_CLI_PASSPHRASE";
+/// If set, used as the BIP39 mnemonic password instead of prompting for it
+const MNEMONIC_PASSWORD_ENV: &str = "POLYMESH_CLI_MNEMONIC_PASSWORD";
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// The kind of secret held by a keystore account
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretKind {
+  /// 32-byte hexadecimal private key
+  Seed,
+  /// BIP39 mnemonic phrase
+  Mnemonic,
+}
+
+/// Argon2id parameters used to derive the encryption key from a passphrase
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Kdf {
+  salt: String,
+  m_cost: u32,
+  t_cost: u32,
+  p_cost: u32,
+}
+
+/// An account secret encrypted with XChaCha20-Poly1305 under a passphrase-derived key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredAccount {
+  /// Public SS58 address (generic substrate format) of the account
+  pub address: String,
+  pub kind: SecretKind,
+  #[serde(default)]
+  pub scheme: Scheme,
+  kdf: Kdf,
+  nonce: String,
+  ciphertext: String,
+}
+
+/// A keystore account, as listed
+#[derive(Serialize, Clone, Debug)]
+pub struct KeyEntry {
+  pub alias: String,
+  pub scheme: Scheme,
+  pub address: String,
+}
+
+impl fmt::Display for KeyEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}\t{}\t{}", self.alias, self.scheme, self.address)
+  }
+}
+
+/// All accounts in the keystore
+#[derive(Serialize, Clone, Debug)]
+pub struct KeyList {
+  pub accounts: Vec<KeyEntry>,
+}
+
+impl fmt::Display for KeyList {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let lines: Vec<String> = self.accounts.iter().map(KeyEntry::to_string).collect();
+    write!(f, "{}", lines.join("\n"))
+  }
+}
+
+/// A newly generated account. Its secrets are shown once and not stored anywhere.
+#[derive(Serialize, Clone, Debug)]
+pub struct GeneratedKey {
+  pub mnemonic: String,
+  pub scheme: Scheme,
+  /// Unset if a soft junction was derived, which leaves the key without a seed of its own
+  pub secret_seed: Option<String>,
+  pub public_key: String,
+  pub testnet_address: String,
+  pub mainnet_address: String,
+}
+
+impl fmt::Display for GeneratedKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "mnemonic: {}", self.mnemonic)?;
+    writeln!(f, "scheme: {}", self.scheme)?;
+    match &self.secret_seed {
+      Some(seed) => writeln!(f, "secret seed: {seed}")?,
+      None => writeln!(f, "secret seed: n/a (soft derivation)")?,
+    }
+    writeln!(f, "public key: {}", self.public_key)?;
+    writeln!(f, "address (testnet): {}", self.testnet_address)?;
+    write!(f, "address (mainnet): {}", self.mainnet_address)
+  }
+}
+
+/// Encrypted on-disk store of named signing accounts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Keystore {
+  version: u32,
+  accounts: BTreeMap<String, StoredAccount>,
+  #[serde(skip)]
+  path: PathBuf,
+}
+
+/// Read a secret from the environment variable `env`, or else prompt for it on the TTY
+fn prompt_secret(env: &str, name: &str, confirm: bool) -> Result<String> {
+  if let Ok(secret) = std::env::var(env) {
+    return Ok(secret);
+  }
+  let secret = rpassword::prompt_password(format!("{name}: "))?;
+  if confirm && rpassword::prompt_password(format!("Repeat {name}: "))? != secret {
+    bail!("{name}s do not match");
+  }
+  Ok(secret)
+}
+
+/// Read the keystore passphrase from the environment, or prompt for it on the TTY
+pub fn passphrase(confirm: bool) -> Result<String> {
+  prompt_secret(PASSPHRASE_ENV, "Keystore passphrase", confirm)
+}
+
+/// Read the BIP39 mnemonic password from the environment, or prompt for it on the TTY
+pub fn mnemonic_password(confirm: bool) -> Result<String> {
+  prompt_secret(MNEMONIC_PASSWORD_ENV, "Mnemonic password", confirm)
+}
+
+fn keystore_path() -> Result<PathBuf> {
+  if let Ok(path) = std::env::var(KEYSTORE_ENV) {
+    return Ok(PathBuf::from(path));
+  }
+  let config_dir = dirs::config_dir().context("could not find the user's config directory")?;
+  Ok(config_dir.join("polymesh-cli").join("keystore.json"))
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<Key> {
+  let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+    .map_err(|e| anyhow!("invalid keystore KDF parameters: {e}"))?;
+  let mut key = Key::default();
+  Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    .hash_password_into(passphrase.as_bytes(), &hex::decode(&kdf.salt)?, &mut key)
+    .map_err(|e| anyhow!("failed to derive keystore key: {e}"))?;
+  Ok(key)
+}
+
+/// Encrypt the secret of an account using signature scheme `scheme` under a passphrase
+pub fn encrypt(secret: &SignerSource, scheme: Scheme, passphrase: &str) -> Result<StoredAccount> {
+  let (kind, plaintext) = match secret {
+    SignerSource::PrivateKey(key) => (SecretKind::Seed, key),
+    SignerSource::Mnemonic(mnemonic) => (SecretKind::Mnemonic, mnemonic),
+    SignerSource::Account(alias) => bail!("{alias} is already a keystore account"),
+  };
+  // Fail early (before anything is written to disk) if the secret is malformed
+  let spec = SignerSpec {
+    scheme: Some(scheme),
+    ..SignerSpec::from(secret.clone())
+  };
+  let address = util::signer(&spec)?.account().to_ss58check();
+
+  let mut salt = [0u8; SALT_LEN];
+  OsRng.fill_bytes(&mut salt);
+  let kdf = Kdf {
+    salt: hex::encode(salt),
+    m_cost: Params::DEFAULT_M_COST,
+    t_cost: Params::DEFAULT_T_COST,
+    p_cost: Params::DEFAULT_P_COST,
+  };
+  let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &kdf)?);
+  let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+  let ciphertext = cipher
+    .encrypt(&nonce, plaintext.as_bytes())
+    .map_err(|_| anyhow!("failed to encrypt account secret"))?;
+
+  Ok(StoredAccount {
+    address,
+    kind,
+    scheme,
+    kdf,
+    nonce: hex::encode(nonce),
+    ciphertext: hex::encode(ciphertext),
+  })
+}
+
+/// Decrypt an account secret with the passphrase it was encrypted under
+pub fn decrypt(account: &StoredAccount, passphrase: &str) -> Result<SignerSource> {
+  let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &account.kdf)?);
+  let nonce = hex::decode(&account.nonce)?;
+  if nonce.len() != 24 {
+    bail!("keystore nonce must be 24 bytes, got {}", nonce.len());
+  }
+  let plaintext = cipher
+    .decrypt(
+      XNonce::from_slice(&nonce),
+      &hex::decode(&account.ciphertext)?[..],
+    )
+    .map_err(|_| Failure::new(ErrorCode::Keystore, "incorrect passphrase"))?;
+  let secret = String::from_utf8(plaintext).context("keystore secret is not valid UTF-8")?;
+  Ok(match account.kind {
+    SecretKind::Seed => SignerSource::PrivateKey(secret),
+    SecretKind::Mnemonic => SignerSource::Mnemonic(secret),
+  })
+}
+
+fn no_account(alias: &str) -> anyhow::Error {
+  Failure::new(
+    ErrorCode::Keystore,
+    format!("no account named {alias} in keystore"),
+  )
+  .into()
+}
+
+impl Keystore {
+  /// Open the keystore under the user's config directory, or an empty one if none exists yet
+  pub fn open() -> Result<Self> {
+    Self::open_at(keystore_path()?)
+  }
+
+  pub fn open_at(path: PathBuf) -> Result<Self> {
+    if !path.exists() {
+      return Ok(Self {
+        version: KEYSTORE_VERSION,
+        accounts: BTreeMap::new(),
+        path,
+      });
+    }
+    let contents = fs::read_to_string(&path)?;
+    let mut keystore: Self = serde_json::from_str(&contents)
+      .with_context(|| format!("malformed keystore at {}", path.display()))?;
+    if keystore.version != KEYSTORE_VERSION {
+      bail!("unsupported keystore version {}", keystore.version);
+    }
+    keystore.path = path;
+    Ok(keystore)
+  }
+
+  /// Write the keystore back to disk, readable only by the current user. The new contents go to a
+  /// temporary file that replaces the keystore once complete, so a failed write leaves the old
+  /// accounts intact.
+  pub fn save(&self) -> Result<()> {
+    if let Some(dir) = self.path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let mut temp_path = self.path.clone().into_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    // Left over from an interrupted save
+    if temp_path.exists() {
+      fs::remove_file(&temp_path)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::OpenOptionsExt;
+      options.mode(0o600);
+    }
+    let mut file = options
+      .open(&temp_path)
+      .with_context(|| format!("could not create {}", temp_path.display()))?;
+    file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &self.path)?;
+    Ok(())
+  }
+
+  pub fn get(&self, alias: &str) -> Result<&StoredAccount> {
+    self.accounts.get(alias).ok_or_else(|| no_account(alias))
+  }
+
+  pub fn insert(&mut self, alias: &str, account: StoredAccount) -> Result<()> {
+    if self.accounts.contains_key(alias) {
+      bail!("an account named {alias} already exists in keystore");
+    }
+    self.accounts.insert(alias.to_string(), account);
+    Ok(())
+  }
+
+  pub fn remove(&mut self, alias: &str) -> Result<StoredAccount> {
+    self.accounts.remove(alias).ok_or_else(|| no_account(alias))
+  }
+
+  pub fn accounts(&self) -> impl Iterator<Item = (&String, &StoredAccount)> {
+    self.accounts.iter()
+  }
+
+  /// Decrypt the secret of the account named `alias`
+  pub fn decrypt(&self, alias: &str, passphrase: &str) -> Result<SignerSource> {
+    decrypt(self.get(alias)?, passphrase).with_context(|| format!("could not unlock {alias}"))
+  }
+}
+
+/// Prompt for an account secret (never taken from argv) and store it under `alias`
+pub fn add(alias: &str, mnemonic: bool, scheme: Scheme) -> Result<KeyEntry> {
+  let mut keystore = Keystore::open()?;
+  let secret = if mnemonic {
+    SignerSource::Mnemonic(rpassword::prompt_password("BIP39 mnemonic phrase: ")?)
+  } else {
+    SignerSource::PrivateKey(rpassword::prompt_password(
+      "32-byte hexadecimal private key: ",
+    )?)
+  };
+  let account = encrypt(&secret, scheme, &passphrase(true)?)?;
+  let address = account.address.clone();
+  keystore.insert(alias, account)?;
+  keystore.save()?;
+  Ok(KeyEntry {
+    alias: String::from(alias),
+    scheme,
+    address,
+  })
+}
+
+/// Import a polkadot{.js} JSON key file into the keystore, under `alias` or else the name
+/// stored in the file
+pub fn import_json(path: &str, alias: Option<&str>) -> Result<KeyEntry> {
+  let mut keystore = Keystore::open()?;
+  let (file, secret) = keyfile::read(path)?;
+  let alias = alias
+    .or_else(|| file.name())
+    .context("key file has no name, so an alias is required")?;
+  let account = encrypt(&secret, Scheme::Sr25519, &passphrase(true)?)?;
+  let address = account.address.clone();
+  keystore.insert(alias, account)?;
+  keystore.save()?;
+  Ok(KeyEntry {
+    alias: String::from(alias),
+    scheme: Scheme::Sr25519,
+    address,
+  })
+}
+
+/// Export a keystore account as a polkadot{.js} JSON key file, encrypted under a new password
+pub fn export_json(alias: &str) -> Result<KeyFile> {
+  let keystore = Keystore::open()?;
+  let scheme = keystore.get(alias)?.scheme;
+  if scheme != Scheme::Sr25519 {
+    bail!("key files only hold sr25519 keys, but {alias} holds a {scheme} key");
+  }
+  let secret = keystore.decrypt(alias, &passphrase(false)?)?;
+  let password = rpassword::prompt_password("Key file password: ")?;
+  if rpassword::prompt_password("Repeat key file password: ")? != password {
+    bail!("passwords do not match");
+  }
+  keyfile::encrypt(&secret, alias, &password)
+}
+
+/// Build a key pair from a secret URI, along with its seed (unless a soft junction was derived)
+fn pair_from_suri<P: Pair>(suri: &str, password: Option<&str>) -> Result<(P, Option<String>)> {
+  let (pair, seed) = P::from_string_with_seed(suri, password)
+    .map_err(|e| anyhow!("invalid derivation path: {e:?}"))?;
+  Ok((
+    pair,
+    seed.map(|seed| String::from("0x") + &hex::encode(seed)),
+  ))
+}
+
+/// Generate a new account from a random BIP39 mnemonic of `words` words (12, 15, 18, 21 or 24),
+/// optionally protected by a password and derived along a `//hard/soft` path
+pub fn generate(
+  words: usize,
+  scheme: Scheme,
+  password: Option<&str>,
+  derivation: Option<&str>,
+) -> Result<GeneratedKey> {
+  if ![12, 15, 18, 21, 24].contains(&words) {
+    bail!("mnemonic must have 12, 15, 18, 21 or 24 words, got {words}");
+  }
+  if let Some(path) = derivation.filter(|path| !path.starts_with('/')) {
+    bail!("derivation path must start with / or //, got {path}");
+  }
+  // Every 3 words of a mnemonic encode 32 bits of entropy
+  let mut entropy = vec![0u8; words / 3 * 4];
+  OsRng.fill_bytes(&mut entropy);
+  let phrase = bip39::Mnemonic::from_entropy(&entropy)?.to_string();
+
+  let suri = format!("{phrase}{}", derivation.unwrap_or_default());
+  let (signer, seed) = match scheme {
+    Scheme::Sr25519 => {
+      let (pair, seed) = pair_from_suri::<sr25519::Pair>(&suri, password)?;
+      (AnySigner::Sr25519(PairSigner::new(pair)), seed)
+    }
+    Scheme::Ed25519 => {
+      let (pair, seed) = pair_from_suri::<ed25519::Pair>(&suri, password)?;
+      (AnySigner::Ed25519(PairSigner::new(pair)), seed)
+    }
+    Scheme::Ecdsa => {
+      let (pair, seed) = pair_from_suri::<ecdsa::Pair>(&suri, password)?;
+      (AnySigner::Ecdsa(PairSigner::new(pair)), seed)
+    }
+  };
+  Ok(GeneratedKey {
+    mnemonic: phrase,
+    scheme,
+    secret_seed: seed,
+    public_key: String::from("0x") + &hex::encode(signer.public_key()),
+    testnet_address: address::signer_ss58check(&signer, &Network::testnet()),
+    mainnet_address: address::signer_ss58check(&signer, &Network::mainnet()),
+  })
+}
+
+/// List the aliases and addresses of all keystore accounts
+pub fn list() -> Result<KeyList> {
+  let keystore = Keystore::open()?;
+  let accounts = keystore
+    .accounts()
+    .map(|(alias, account)| KeyEntry {
+      alias: alias.clone(),
+      scheme: account.scheme,
+      address: account.address.clone(),
+    })
+    .collect();
+  Ok(KeyList { accounts })
+}
+
+/// Delete an account from the keystore, returning what it was
+pub fn remove(alias: &str) -> Result<KeyEntry> {
+  let mut keystore = Keystore::open()?;
+  let account = keystore.remove(alias)?;
+  keystore.save()?;
+  Ok(KeyEntry {
+    alias: String::from(alias),
+    scheme: account.scheme,
+    address: account.address,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PRIVATE_KEY: &str = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+
+  #[test]
+  fn it_round_trips_an_encrypted_secret() {
+    let secret = SignerSource::PrivateKey(String::from(PRIVATE_KEY));
+    let account = encrypt(&secret, Scheme::Sr25519, "correct horse").unwrap();
+    assert_eq!(
+      account.address,
+      "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"
+    );
+    assert_eq!(decrypt(&account, "correct horse").unwrap(), secret);
+  }
+
+  #[test]
+  fn it_rejects_the_wrong_passphrase() {
+    let secret = SignerSource::PrivateKey(String::from(PRIVATE_KEY));
+    let account = encrypt(&secret, Scheme::Sr25519, "correct horse").unwrap();
+    assert!(decrypt(&account, "battery staple").is_err());
+  }
+
+  #[test]
+  fn it_generates_mnemonics_of_each_length() {
+    for words in [12, 15, 18, 21, 24] {
+      let generated = generate(words, Scheme::Sr25519, None, None).unwrap();
+      assert_eq!(generated.mnemonic.split_whitespace().count(), words);
+    }
+    assert!(generate(13, Scheme::Sr25519, None, None).is_err());
+  }
+
+  #[test]
+  fn it_generates_derived_accounts() {
+    let hard = generate(12, Scheme::Sr25519, Some("password"), Some("//polymesh//0")).unwrap();
+    assert!(hard.secret_seed.unwrap().starts_with("0x"));
+    let soft = generate(12, Scheme::Sr25519, None, Some("//polymesh/0")).unwrap();
+    assert!(soft.secret_seed.is_none());
+    assert!(generate(12, Scheme::Sr25519, None, Some("polymesh")).is_err());
+  }
+
+  #[test]
+  fn it_saves_and_reopens_a_keystore() {
+    let path =
+      std::env::temp_dir().join(format!("polymesh-cli-keystore-{}.json", std::process::id()));
+    let secret = SignerSource::PrivateKey(String::from(PRIVATE_KEY));
+    let mut keystore = Keystore::open_at(path.clone()).unwrap();
+    keystore
+      .insert(
+        "alice",
+        encrypt(&secret, Scheme::Sr25519, "correct horse").unwrap(),
+      )
+      .unwrap();
+    keystore.save().unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(&path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let keystore = Keystore::open_at(path.clone()).unwrap();
+    assert_eq!(keystore.decrypt("alice", "correct horse").unwrap(), secret);
+    assert!(keystore.get("bob").is_err());
+    fs::remove_file(path).unwrap();
+  }
+}
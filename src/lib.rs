@@ -1,41 +1,189 @@
-use anyhow::Result;
+use amount::Balance;
+use anyhow::{Context, Result};
+use block::BlockId;
+use clap::ArgMatches;
+use network::Network;
+use output::{Format, Report};
+use permissions::{PermissionSet, Restriction};
+use secondary::{NewSecondary, SignedSecondary};
+use util::{Scheme, SignerSource, SignerSpec, TxOptions};
 
 mod command;
+pub mod output;
 mod util;
 
+pub use command::command;
+
 mod address;
+mod amount;
 mod balance;
+mod block;
+mod chain;
+mod decode;
+mod extrinsic;
+mod identity;
+mod keyfile;
+mod keys;
+mod metadata;
+mod network;
+mod offline;
+mod permissions;
 mod secondary;
 mod signing;
 mod staking;
 mod transaction;
 
-pub async fn run() -> Result<String> {
-  let res = match command::command().get_matches().subcommand() {
+/// Resolve the signing account from a keystore alias (`account`), a BIP39 mnemonic (`mnemonic`)
+/// or a hexadecimal private key (`key`), in that order of precedence, using signature scheme
+/// `scheme`. If `password` is set, the mnemonic password is read from the environment or prompted
+/// for, and `derive` is applied as a derivation path.
+fn signer_spec(sub_m: &ArgMatches) -> Result<SignerSpec> {
+  let get = |id: &str| sub_m.try_get_one::<String>(id).ok().flatten().cloned();
+  let source = if let Some(alias) = get("account") {
+    SignerSource::Account(alias)
+  } else if let Some(mnemonic) = get("mnemonic") {
+    SignerSource::Mnemonic(mnemonic)
+  } else {
+    SignerSource::PrivateKey(get("key").expect("requires either key, mnemonic or account"))
+  };
+  let password = match sub_m.try_get_one::<bool>("password") {
+    Ok(Some(true)) => Some(keys::mnemonic_password(false)?),
+    _ => None,
+  };
+  Ok(SignerSpec {
+    source,
+    scheme: scheme(sub_m)?,
+    password,
+    derivation: get("derive"),
+  })
+}
+
+/// Parse the `scheme` argument of a subcommand, if present
+fn scheme(sub_m: &ArgMatches) -> Result<Option<Scheme>> {
+  match sub_m.try_get_one::<String>("scheme") {
+    Ok(Some(scheme)) => Ok(Some(scheme.parse()?)),
+    _ => Ok(None),
+  }
+}
+
+/// Parse the amount argument `id` of a subcommand, as a whole number of μPOLYX if `raw` is set or
+/// else as POLYX with an optional unit
+fn amount(sub_m: &ArgMatches, id: &str) -> Result<Balance> {
+  let value = sub_m.get_one::<String>(id).expect("amount required");
+  let amount = if sub_m.get_flag("raw") {
+    Balance::parse_raw(value)
+  } else {
+    value.parse()
+  };
+  amount.with_context(|| format!("invalid {id} {value}"))
+}
+
+/// The permissions of a secondary key, from a policy file or else from the `assets`,
+/// `portfolios` and `extrinsics` arguments of a subcommand and their `except` variants
+fn permission_set(sub_m: &ArgMatches) -> Result<PermissionSet> {
+  if let Some(path) = sub_m.get_one::<String>("permissions") {
+    return PermissionSet::read(path);
+  }
+  let restriction = |id: &str| {
+    let items =
+      |id: &str| -> Option<Vec<String>> { Some(sub_m.get_many::<String>(id)?.cloned().collect()) };
+    Restriction::new(items(id), items(&format!("except_{id}")))
+  };
+  Ok(PermissionSet {
+    assets: restriction("assets"),
+    portfolios: restriction("portfolios"),
+    extrinsics: restriction("extrinsics"),
+  })
+}
+
+/// Parse the `at` argument of a subcommand, if given
+fn block_id(sub_m: &ArgMatches) -> Result<Option<BlockId>> {
+  match sub_m.get_one::<String>("at") {
+    Some(at) => Ok(Some(at.parse()?)),
+    None => Ok(None),
+  }
+}
+
+/// Resolve the network selected by the global `network` and `url` arguments, falling back to the
+/// config file's default network
+fn selected_network(matches: &ArgMatches) -> Result<Network> {
+  let name = matches.get_one::<String>("network");
+  let url = matches.get_one::<String>("url");
+  network::Config::open()?.network(name.map(|s| s.as_str()), url.map(|s| s.as_str()))
+}
+
+/// How to sign and submit transactions, as selected by the global `wait`, `dry-run`, `nonce`,
+/// `mortality` and `tip` arguments
+fn tx_options(matches: &ArgMatches) -> Result<TxOptions> {
+  let get = |id: &str| matches.get_one::<String>(id).map(|value| value.as_str());
+  let tip = match get("tip") {
+    Some(tip) => tip.parse().with_context(|| format!("invalid tip {tip}"))?,
+    None => Balance::default(),
+  };
+  Ok(TxOptions {
+    wait: get("wait").map(str::parse).transpose()?.unwrap_or_default(),
+    dry_run: matches.get_flag("dry_run"),
+    nonce: get("nonce")
+      .map(str::parse)
+      .transpose()?
+      .unwrap_or_default(),
+    mortality: get("mortality")
+      .map(str::parse)
+      .transpose()?
+      .unwrap_or_default(),
+    tip,
+  })
+}
+
+/// The format selected by the global `output` argument
+pub fn output_format(matches: &ArgMatches) -> Format {
+  matches
+    .get_one::<String>("output")
+    .and_then(|format| format.parse().ok())
+    .unwrap_or_default()
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<Box<dyn Report>> {
+  // Only commands that connect need a network and transaction options, so that offline ones work
+  // without a readable config file
+  let network = || selected_network(matches);
+  let tx = || tx_options(matches);
+  let res: Box<dyn Report> = match matches.subcommand() {
     // Subcommand: send
-    Some(("send", sub_m)) => {
-      let amount_polyx = sub_m.get_one::<f64>("amount").expect("amount required");
-      let amount = (*amount_polyx * 1e6) as u128; // convert POLYX to μPOLYX
-      let destination = sub_m
-        .get_one::<String>("destination")
-        .expect("destination required");
-      let mainnet = sub_m.get_flag("mainnet");
-      match sub_m.get_one::<String>("key") {
-        Some(key) => transaction::withdraw(key, destination, amount, mainnet).await?,
-        None => {
-          let mnemonic = sub_m
-            .get_one::<String>("mnemonic")
-            .expect("requires either key or mnemonic");
-          transaction::withdraw_with_mnemonic(mnemonic, destination, amount, mainnet).await?
-        }
+    Some(("send", sub_m)) => match sub_m.subcommand() {
+      Some(("batch", sub_m)) => {
+        let file = sub_m.get_one::<String>("file").expect("file required");
+        let items = transaction::read_batch(file, sub_m.get_flag("raw"))?;
+        let max_per_batch = *sub_m
+          .get_one::<u32>("max_per_batch")
+          .expect("default max per batch");
+        let signer = signer_spec(sub_m)?;
+        Box::new(
+          transaction::batch(&signer, &items, max_per_batch as usize, &network()?, &tx()?).await?,
+        )
       }
-    }
+      _ => {
+        let amount = amount(sub_m, "amount")?;
+        let destination = sub_m
+          .get_one::<String>("destination")
+          .expect("destination required");
+        Box::new(
+          transaction::withdraw(
+            &signer_spec(sub_m)?,
+            destination,
+            amount,
+            &network()?,
+            &tx()?,
+          )
+          .await?,
+        )
+      }
+    },
 
     // Subcommand: sign
     Some(("sign", sub_m)) => {
-      let key = sub_m.get_one::<String>("key").expect("key required");
       let payload = sub_m.get_one::<String>("payload").expect("key required");
-      signing::sign_payload(key, payload).await?
+      Box::new(signing::sign_payload(&signer_spec(sub_m)?, payload).await?)
     }
 
     // Subcommand: verify
@@ -49,117 +197,304 @@ pub async fn run() -> Result<String> {
       let signature = sub_m
         .get_one::<String>("signature")
         .expect("signature required");
-      signing::verify_signature(signature, address, payload).to_string()
+      Box::new(signing::verify(signature, address, payload)?)
     }
 
     // Subcommand: address
-    Some(("address", sub_m)) => {
-      let mainnet = sub_m.get_flag("mainnet");
-      match sub_m.get_one::<String>("mnemonic") {
-        Some(mnemonic) => address::mnemonic_to_ss58check(mnemonic, mainnet, None)?,
-        None => {
-          let key = sub_m.get_one::<String>("key").expect("key required");
-          address::private_key_to_ss58check(key, mainnet)?
+    Some(("address", sub_m)) => Box::new(address::report(&signer_spec(sub_m)?, &network()?)?),
+
+    // Subcommand: balance
+    Some(("balance", sub_m)) => match sub_m.subcommand() {
+      Some(("watch", sub_m)) => {
+        let addresses: Vec<String> = sub_m
+          .get_many::<String>("addresses")
+          .expect("addresses required")
+          .cloned()
+          .collect();
+        let finalized = sub_m.get_flag("finalized");
+        let format = output_format(matches);
+        let on_change = |change: balance::BalanceChange| format.print_event(&change);
+        Box::new(balance::watch(&addresses, &network()?, finalized, on_change).await?)
+      }
+      _ => {
+        let mut addresses: Vec<String> = sub_m
+          .get_many::<String>("addresses")
+          .map(|addresses| addresses.cloned().collect())
+          .unwrap_or_default();
+        let file = sub_m.get_one::<String>("file");
+        if let Some(file) = file {
+          addresses.extend(balance::read_addresses(file)?);
+        }
+        let csv = sub_m.get_one::<String>("csv");
+        // A single address gets the full breakdown, and anything more a table
+        if let ([address], None, None) = (addresses.as_slice(), file, csv) {
+          Box::new(balance::report(address, &network()?, block_id(sub_m)?).await?)
+        } else {
+          let table = balance::table(&addresses, &network()?, block_id(sub_m)?).await?;
+          if let Some(csv) = csv {
+            std::fs::write(csv, table.report.to_csv())
+              .with_context(|| format!("could not write CSV to {csv}"))?;
+          }
+          Box::new(table)
         }
       }
-    }
+    },
 
-    // Subcommand: balance
-    Some(("balance", sub_m)) => {
+    // Subcommand: did
+    Some(("did", sub_m)) => {
       let address = sub_m
         .get_one::<String>("address")
         .expect("address required");
-      let mainnet = sub_m.get_flag("mainnet");
-      let bal = if sub_m.get_flag("staked") {
-        balance::staked(address, mainnet).await?
-      } else {
-        balance::free(address, mainnet).await?
-      };
-      format!("{bal}",)
+      Box::new(identity::did(address, &network()?, block_id(sub_m)?).await?)
     }
 
     // Subcommand: secondary (i.e. Secondary keys)
     Some(("secondary", sub_m)) => match sub_m.subcommand() {
+      Some(("list", sub_m)) => {
+        let who = sub_m.get_one::<String>("who").expect("who required");
+        Box::new(secondary::list(who, &network()?, block_id(sub_m)?).await?)
+      }
       Some(("add", sub_m)) => {
-        let mnemonic = sub_m
-          .get_one::<String>("mnemonic")
-          .expect("mnemonic required");
-        let secondary_key = sub_m
-          .get_one::<String>("secondary_key")
-          .expect("secondary key required");
+        // Secondaries given on the command line, or by their signatures, share the permissions
+        // given there, which are also the default for those listed in a secondaries file
+        let permissions = permission_set(sub_m)?;
+        let sources = |id: &str| sub_m.get_many::<String>(id).into_iter().flatten().cloned();
+        let mut secondaries: Vec<NewSecondary> = sources("secondary_key")
+          .map(SignerSource::PrivateKey)
+          .chain(sources("secondary_account").map(SignerSource::Account))
+          .map(|source| NewSecondary {
+            signer: source.into(),
+            permissions: permissions.clone(),
+          })
+          .collect();
+        if let Some(path) = sub_m.get_one::<String>("secondaries") {
+          secondaries.extend(secondary::read_secondaries(path, &permissions)?);
+        }
+        let signed = sources("signatures")
+          .map(|path| {
+            Ok(SignedSecondary {
+              signature: secondary::read_auth_signature(&path)?,
+              permissions: permissions.clone(),
+            })
+          })
+          .collect::<Result<Vec<_>>>()?;
+        Box::new(
+          secondary::add(
+            &signer_spec(sub_m)?,
+            &secondaries,
+            &signed,
+            sub_m.get_one::<u64>("expires_after").copied(),
+            &network()?,
+            &tx()?,
+          )
+          .await?,
+        )
+      }
+      Some(("auth-request", sub_m)) => {
+        let primary = sub_m
+          .get_one::<String>("primary")
+          .expect("primary required");
         let expires_after = sub_m
           .get_one::<u64>("expires_after")
           .expect("expiry required");
-        let mainnet = sub_m.get_flag("mainnet");
-        secondary::add(mnemonic, secondary_key, *expires_after, mainnet).await?
+        let request = secondary::auth_request(primary, *expires_after, &network()?).await?;
+        if let Some(out) = sub_m.get_one::<String>("out") {
+          std::fs::write(out, serde_json::to_string_pretty(&request)?)
+            .with_context(|| format!("could not write the request to {out}"))?;
+        }
+        Box::new(request)
+      }
+      Some(("auth-sign", sub_m)) => {
+        let path = sub_m
+          .get_one::<String>("request")
+          .expect("request required");
+        let request = secondary::read_auth_request(path)?;
+        let signature = secondary::auth_sign(&request, &signer_spec(sub_m)?).await?;
+        if let Some(out) = sub_m.get_one::<String>("out") {
+          std::fs::write(out, serde_json::to_string_pretty(&signature)?)
+            .with_context(|| format!("could not write the signature to {out}"))?;
+        }
+        Box::new(signature)
       }
       Some(("remove", sub_m)) => {
-        let mnemonic = sub_m
-          .get_one::<String>("mnemonic")
-          .expect("mnemonic required");
         let who = sub_m.get_one::<String>("who").expect("who required");
-        let mainnet = sub_m.get_flag("mainnet");
-        secondary::remove(mnemonic, who, mainnet).await?
+        Box::new(secondary::remove(&signer_spec(sub_m)?, who, &network()?, &tx()?).await?)
       }
+      Some(("set-permissions", sub_m)) => {
+        let who = sub_m.get_one::<String>("who").expect("who required");
+        let permissions = permission_set(sub_m)?;
+        Box::new(
+          secondary::set_permissions(&signer_spec(sub_m)?, who, &permissions, &network()?, &tx()?)
+            .await?,
+        )
+      }
+      Some((command @ ("freeze" | "unfreeze"), sub_m)) => Box::new(
+        secondary::freeze(
+          &signer_spec(sub_m)?,
+          command == "freeze",
+          &network()?,
+          &tx()?,
+        )
+        .await?,
+      ),
       _ => unreachable!(),
     },
 
+    // Subcommand: keys
+    Some(("keys", sub_m)) => match sub_m.subcommand() {
+      Some(("add", sub_m)) => {
+        let alias = sub_m.get_one::<String>("alias").expect("alias required");
+        let scheme = scheme(sub_m)?.unwrap_or_default();
+        Box::new(keys::add(alias, sub_m.get_flag("mnemonic"), scheme)?)
+      }
+      Some(("generate", sub_m)) => {
+        let words = sub_m.get_one::<usize>("words").expect("default word count");
+        let password = if sub_m.get_flag("password") {
+          Some(keys::mnemonic_password(true)?)
+        } else {
+          None
+        };
+        let derivation = sub_m.get_one::<String>("derive");
+        let scheme = scheme(sub_m)?.unwrap_or_default();
+        Box::new(keys::generate(
+          *words,
+          scheme,
+          password.as_deref(),
+          derivation.map(|s| s.as_str()),
+        )?)
+      }
+      Some(("import-json", sub_m)) => {
+        let file = sub_m.get_one::<String>("file").expect("file required");
+        let alias = sub_m.get_one::<String>("alias");
+        Box::new(keys::import_json(file, alias.map(|s| s.as_str()))?)
+      }
+      Some(("export-json", sub_m)) => {
+        let alias = sub_m.get_one::<String>("alias").expect("alias required");
+        Box::new(keys::export_json(alias)?)
+      }
+      Some(("list", _)) => Box::new(keys::list()?),
+      Some(("remove", sub_m)) => {
+        let alias = sub_m.get_one::<String>("alias").expect("alias required");
+        Box::new(keys::remove(alias)?)
+      }
+      _ => unreachable!(), // subcommand required
+    },
+
     // Subcommand: staking
     Some(("staking", sub_m)) => match sub_m.subcommand() {
-      Some(("validators", sub_m)) => {
-        let mainnet = sub_m.get_flag("mainnet");
-        let operators = staking::validators(mainnet).await?;
-        operators
-          .iter()
-          .fold(String::new(), |acc, operator| acc + operator + "\n")
-          .strip_suffix('\n')
-          .expect("there should be a new line at the end of iter")
-          .to_string()
+      Some(("validators", _)) => Box::new(staking::validators(&network()?).await?),
+      Some(("ledger", sub_m)) => {
+        let address = sub_m
+          .get_one::<String>("address")
+          .expect("address required");
+        Box::new(staking::ledger(address, &network()?, block_id(sub_m)?).await?)
       }
       Some(("nominate", sub_m)) => {
-        let controller_key = sub_m
-          .get_one::<String>("key")
-          .expect("controller key required");
         let validators: Vec<&str> = sub_m
           .get_many::<String>("validators")
           .expect("validators required")
           .map(|s| s.as_str())
           .collect();
-        let mainnet = sub_m.get_flag("mainnet");
-        staking::nominate(controller_key, validators, mainnet).await?
+        Box::new(staking::nominate(&signer_spec(sub_m)?, validators, &network()?, &tx()?).await?)
       }
       Some(("bond", sub_m)) => {
-        let stash_key = sub_m.get_one::<String>("key").expect("stash key required");
         let controller = sub_m
           .get_one::<String>("controller")
           .expect("controller address required");
-        let value_polyx = sub_m.get_one::<f64>("value").expect("value required");
-        let value = (*value_polyx * 1e6) as u128; // convert POLYX to μPOLYX
-        let mainnet = sub_m.get_flag("mainnet");
-        staking::bond(stash_key, controller, value, mainnet).await?
+        let value = amount(sub_m, "value")?;
+        Box::new(staking::bond(&signer_spec(sub_m)?, controller, value, &network()?, &tx()?).await?)
       }
       Some(("unbond", sub_m)) => {
-        let controller_key = sub_m
-          .get_one::<String>("key")
-          .expect("controller key required");
-        let value_polyx = sub_m.get_one::<f64>("value").expect("value required");
-        let value = (*value_polyx * 1e6) as u128; // convert POLYX to μPOLYX
-        let mainnet = sub_m.get_flag("mainnet");
-        staking::unbond(controller_key, value, mainnet).await?
+        let value = amount(sub_m, "value")?;
+        Box::new(staking::unbond(&signer_spec(sub_m)?, value, &network()?, &tx()?).await?)
       }
       Some(("extra", sub_m)) => {
-        let stash_key = sub_m.get_one::<String>("key").expect("stash key required");
-        let value_polyx = sub_m.get_one::<f64>("value").expect("value required");
-        let value = (*value_polyx * 1e6) as u128; // convert POLYX to μPOLYX
-        let mainnet = sub_m.get_flag("mainnet");
-        staking::bond_extra(stash_key, value, mainnet).await?
+        let value = amount(sub_m, "value")?;
+        Box::new(staking::bond_extra(&signer_spec(sub_m)?, value, &network()?, &tx()?).await?)
       }
       Some(("withdraw", sub_m)) => {
-        let controller_key = sub_m
-          .get_one::<String>("key")
-          .expect("controller key required");
-        let mainnet = sub_m.get_flag("mainnet");
-        staking::withdraw_unbonded(controller_key, mainnet).await?
+        Box::new(staking::withdraw_unbonded(&signer_spec(sub_m)?, &network()?, &tx()?).await?)
+      }
+      _ => unreachable!(), // subcommand required
+    },
+
+    // Subcommand: tx (i.e. offline signing)
+    Some(("tx", sub_m)) => match sub_m.subcommand() {
+      Some(("build", sub_m)) => {
+        let from = sub_m.get_one::<String>("from").expect("from required");
+        let call = match sub_m.subcommand() {
+          Some(("send", sub_m)) => offline::OfflineCall::Transfer {
+            dest: sub_m
+              .get_one::<String>("destination")
+              .expect("destination required")
+              .clone(),
+            amount: amount(sub_m, "amount")?,
+          },
+          Some(("nominate", sub_m)) => offline::OfflineCall::Nominate {
+            validators: sub_m
+              .get_many::<String>("validators")
+              .expect("validators required")
+              .cloned()
+              .collect(),
+          },
+          Some(("bond", sub_m)) => offline::OfflineCall::Bond {
+            controller: sub_m
+              .get_one::<String>("controller")
+              .expect("controller address required")
+              .clone(),
+            value: amount(sub_m, "value")?,
+          },
+          Some(("unbond", sub_m)) => offline::OfflineCall::Unbond {
+            value: amount(sub_m, "value")?,
+          },
+          Some(("extra", sub_m)) => offline::OfflineCall::BondExtra {
+            value: amount(sub_m, "value")?,
+          },
+          Some(("withdraw", _)) => offline::OfflineCall::WithdrawUnbonded,
+          _ => unreachable!(), // subcommand required
+        };
+        let payload = offline::build(&call, from, &network()?, &tx()?).await?;
+        if let Some(out) = sub_m.get_one::<String>("out") {
+          std::fs::write(out, serde_json::to_string_pretty(&payload)?)
+            .with_context(|| format!("could not write the transaction to {out}"))?;
+        }
+        Box::new(payload)
+      }
+      Some(("sign", sub_m)) => {
+        let path = sub_m
+          .get_one::<String>("payload")
+          .expect("payload required");
+        let payload = offline::read_payload(path)?;
+        let signed = offline::sign(&payload, &signer_spec(sub_m)?).await?;
+        if let Some(out) = sub_m.get_one::<String>("out") {
+          std::fs::write(out, &signed.extrinsic)
+            .with_context(|| format!("could not write the extrinsic to {out}"))?;
+        }
+        Box::new(signed)
+      }
+      Some(("submit", sub_m)) => {
+        let extrinsic = match sub_m.get_one::<String>("file") {
+          Some(file) => std::fs::read_to_string(file)
+            .with_context(|| format!("could not read the extrinsic from {file}"))?,
+          None => sub_m
+            .get_one::<String>("extrinsic")
+            .expect("extrinsic required")
+            .clone(),
+        };
+        Box::new(offline::submit(&extrinsic, &network()?, &tx()?).await?)
+      }
+      _ => unreachable!(), // subcommand required
+    },
+
+    // Subcommand: decode
+    Some(("decode", sub_m)) => match sub_m.subcommand() {
+      Some(("extrinsic", sub_m)) => {
+        let hex = sub_m.get_one::<String>("hex").expect("hex required");
+        Box::new(decode::extrinsic(hex, &network()?).await?)
+      }
+      Some(("call", sub_m)) => {
+        let hex = sub_m.get_one::<String>("hex").expect("hex required");
+        Box::new(decode::call(hex, &network()?).await?)
       }
       _ => unreachable!(), // subcommand required
     },
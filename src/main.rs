@@ -1,9 +1,34 @@
+use polymesh_cli::output::{ErrorReport, Format};
+
 #[tokio::main]
 async fn main() {
-  match polymesh_cli::run().await {
-    Ok(res) => println!("{}", res),
+  let matches = polymesh_cli::command().get_matches();
+  let format = polymesh_cli::output_format(&matches);
+  match polymesh_cli::run(&matches).await {
+    Ok(res) => match format {
+      Format::Text => println!("{}", res),
+      Format::Json => println!(
+        "{}",
+        serde_json::to_string_pretty(&res).expect("results serialize to JSON")
+      ),
+      Format::Yaml => print!(
+        "{}",
+        serde_yaml::to_string(&res).expect("results serialize to YAML")
+      ),
+    },
     Err(e) => {
-      eprintln!("{}", e);
+      let error = ErrorReport::from(&e);
+      match format {
+        Format::Text => eprintln!("{}", e),
+        Format::Json => eprintln!(
+          "{}",
+          serde_json::to_string(&error).expect("errors serialize to JSON")
+        ),
+        Format::Yaml => eprint!(
+          "{}",
+          serde_yaml::to_string(&error).expect("errors serialize to YAML")
+        ),
+      }
       std::process::exit(1);
     }
   }
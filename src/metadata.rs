@@ -0,0 +1,567 @@
+use crate::{amount::Balance, chain::ChainContext};
+use anyhow::{anyhow, bail, Context, Result};
+use frame_metadata::{
+  v14::{RuntimeMetadataV14, StorageEntryType, StorageHasher},
+  RuntimeMetadata, RuntimeMetadataPrefixed,
+};
+use jsonrpsee_core::rpc_params;
+use parity_scale_codec::Decode;
+use polymesh_api::client::{
+  sp_core::{
+    crypto::{Ss58AddressFormat, Ss58Codec},
+    hashing::{blake2_128, blake2_256, twox_128, twox_256, twox_64},
+  },
+  AccountId, BlockHash,
+};
+use scale_info::{form::PortableForm, Field, TypeDef, TypeDefPrimitive};
+use scale_value::{At, Composite, Primitive, Value, ValueDef, Variant};
+use serde::Serialize;
+use std::fmt;
+
+/// Runtime metadata of a chain, for naming and decoding its calls, events and errors
+pub struct Metadata {
+  runtime: RuntimeMetadataV14,
+  /// SS58 address format that decoded account IDs are rendered in
+  ss58_format: u16,
+  /// Token symbol that decoded balances are rendered in, or `None` to leave them in μPOLYX
+  balance_symbol: Option<String>,
+}
+
+/// An event emitted by a pallet, e.g. `Balances.Transfer`
+#[derive(Serialize, Clone, Debug)]
+pub struct EventReport {
+  pub pallet: String,
+  pub name: String,
+  pub fields: Composite<()>,
+}
+
+impl fmt::Display for EventReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.pallet, self.name)?;
+    if !self.fields.is_empty() {
+      write!(f, " {}", self.fields)?;
+    }
+    Ok(())
+  }
+}
+
+/// A call to a pallet, e.g. `Balances.transfer`
+#[derive(Serialize, Clone, Debug)]
+pub struct CallReport {
+  pub pallet: String,
+  pub name: String,
+  pub args: Composite<()>,
+}
+
+impl fmt::Display for CallReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.pallet, self.name)?;
+    if !self.args.is_empty() {
+      write!(f, " {}", self.args)?;
+    }
+    Ok(())
+  }
+}
+
+/// An event along with the extrinsic that emitted it, if any
+#[derive(Clone, Debug)]
+pub struct EventRecord {
+  /// Index of the extrinsic in its block, or `None` for events emitted outside of extrinsics
+  pub extrinsic_index: Option<u32>,
+  pub event: EventReport,
+}
+
+/// A signed extension of the runtime's transactions, e.g. `CheckNonce`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedExtension {
+  pub identifier: String,
+  /// Type of the data the extension adds to the transaction
+  pub ty: u32,
+  /// Whether the extension adds data to the transaction or to its signed payload
+  pub has_data: bool,
+}
+
+/// Why an extrinsic failed to dispatch, e.g. `Balances.InsufficientBalance`
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct DispatchErrorReport {
+  pub name: String,
+  /// The error's documentation, for errors raised by a pallet
+  pub docs: String,
+}
+
+impl fmt::Display for DispatchErrorReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.docs.as_str() {
+      "" => write!(f, "{}", self.name),
+      docs => write!(f, "{}: {docs}", self.name),
+    }
+  }
+}
+
+impl Metadata {
+  /// Fetch the metadata of the runtime at block `at`, or else at the latest block
+  pub async fn fetch(chain: &ChainContext, at: Option<BlockHash>) -> Result<Self> {
+    let at = at.map(|hash| String::from("0x") + &hex::encode(hash));
+    let client = chain.api.client();
+    let metadata: String = client.request("state_getMetadata", rpc_params!(at)).await?;
+    let bytes = hex::decode(metadata.trim_start_matches("0x"))?;
+    Self::decode(&bytes, chain.ss58_format)
+  }
+
+  /// Decode SCALE-encoded metadata, which must be V14
+  pub fn decode(bytes: &[u8], ss58_format: u16) -> Result<Self> {
+    let prefixed =
+      RuntimeMetadataPrefixed::decode(&mut &bytes[..]).context("malformed runtime metadata")?;
+    match prefixed.1 {
+      RuntimeMetadata::V14(runtime) => Ok(Self {
+        runtime,
+        ss58_format,
+        balance_symbol: None,
+      }),
+      other => bail!("unsupported runtime metadata version {}", other.version()),
+    }
+  }
+
+  /// Render decoded balances as amounts of `symbol`, e.g. `1.5 POLYX`, rather than in μPOLYX
+  pub fn render_balances(&mut self, symbol: &str) {
+    self.balance_symbol = Some(String::from(symbol));
+  }
+
+  /// The signed extensions the runtime expects on transactions, in order
+  pub fn signed_extensions(&self) -> Vec<SignedExtension> {
+    let is_unit = |ty: u32| match self.runtime.types.resolve(ty).map(|ty| &ty.type_def) {
+      Some(TypeDef::Tuple(def)) => def.fields.is_empty(),
+      Some(TypeDef::Composite(def)) => def.fields.is_empty(),
+      _ => false,
+    };
+    self
+      .runtime
+      .extrinsic
+      .signed_extensions
+      .iter()
+      .map(|ext| SignedExtension {
+        identifier: ext.identifier.clone(),
+        ty: ext.ty.id,
+        has_data: !is_unit(ext.ty.id) || !is_unit(ext.additional_signed.id),
+      })
+      .collect()
+  }
+
+  /// Whether transactions address their signer with a `MultiAddress` rather than an account ID
+  pub fn multi_address(&self) -> bool {
+    let address = self
+      .extrinsic_param("Address")
+      .and_then(|ty| self.runtime.types.resolve(ty));
+    match address {
+      Some(ty) => matches!(ty.type_def, TypeDef::Variant(_)),
+      None => true,
+    }
+  }
+
+  /// Decode a SCALE-encoded call, which must have no trailing bytes
+  pub fn decode_call(&self, bytes: &[u8]) -> Result<CallReport> {
+    let ty = self
+      .extrinsic_param("Call")
+      .context("the runtime metadata has no call type")?;
+    let mut input = bytes;
+    let value = self.decode_from(&mut input, ty).context("malformed call")?;
+    if !input.is_empty() {
+      bail!("malformed call: {} trailing bytes", input.len());
+    }
+    let (pallet, call) = nested_variant(&value).context("malformed call")?;
+    Ok(CallReport {
+      pallet: pallet.name,
+      name: call.name,
+      args: call.values,
+    })
+  }
+
+  /// Names of the calls of pallet `pallet`, or `None` if the runtime has no such pallet
+  pub fn call_names(&self, pallet: &str) -> Option<Vec<String>> {
+    let pallet = self.runtime.pallets.iter().find(|p| p.name == pallet)?;
+    let calls = pallet
+      .calls
+      .as_ref()
+      .and_then(|calls| self.runtime.types.resolve(calls.ty.id));
+    Some(match calls.map(|ty| &ty.type_def) {
+      Some(TypeDef::Variant(def)) => def.variants.iter().map(|v| v.name.clone()).collect(),
+      _ => Vec::new(),
+    })
+  }
+
+  /// The ID of the type at `path`, e.g. `["sp_runtime", "DispatchError"]`
+  pub fn type_id(&self, path: &[&str]) -> Option<u32> {
+    let types = &self.runtime.types.types;
+    let ty = types.iter().find(|ty| ty.ty.path.segments == path)?;
+    Some(ty.id)
+  }
+
+  /// Name and documentation of error `error` of the pallet at index `pallet`
+  pub fn module_error(&self, pallet: u8, error: u8) -> Option<DispatchErrorReport> {
+    let pallet = self.runtime.pallets.iter().find(|p| p.index == pallet)?;
+    let ty = self.runtime.types.resolve(pallet.error.as_ref()?.ty.id)?;
+    let variant = match &ty.type_def {
+      TypeDef::Variant(def) => def.variants.iter().find(|v| v.index == error)?,
+      _ => return None,
+    };
+    Some(DispatchErrorReport {
+      name: format!("{}.{}", pallet.name, variant.name),
+      docs: variant.docs.join(" "),
+    })
+  }
+
+  /// Describe a decoded `DispatchError`, naming pallet errors from the metadata
+  pub fn dispatch_error<T>(&self, error: &Value<T>) -> DispatchErrorReport {
+    let module = match &error.value {
+      ValueDef::Variant(variant) if variant.name == "Module" => {
+        // Older runtimes have `Module { index, error }`, newer `Module(ModuleError { index, error })`
+        let module = if error.at("index").is_some() {
+          Some(error)
+        } else {
+          error.at(0)
+        };
+        let index = module.at("index").and_then(Value::as_u128);
+        // The error is a single byte, or four bytes of which the first is the error's index. Once
+        // decoded, the four bytes are rendered as hexadecimal.
+        let code = module.at("error").and_then(|code| match code.as_str() {
+          Some(bytes) => hex::decode(bytes.trim_start_matches("0x"))
+            .ok()?
+            .first()
+            .copied()
+            .map(u128::from),
+          None => code.as_u128().or_else(|| code.at(0)?.as_u128()),
+        });
+        match (index, code) {
+          (Some(index), Some(code)) => self.module_error(index as u8, code as u8),
+          _ => None,
+        }
+      }
+      _ => None,
+    };
+    module.unwrap_or_else(|| DispatchErrorReport {
+      name: error.to_string(),
+      docs: String::new(),
+    })
+  }
+
+  /// Fetch and decode the events emitted in block `hash`
+  pub async fn events(&self, chain: &ChainContext, hash: BlockHash) -> Result<Vec<EventRecord>> {
+    let key = [twox_128(b"System"), twox_128(b"Events")].concat();
+    let params = rpc_params!(
+      String::from("0x") + &hex::encode(key),
+      String::from("0x") + &hex::encode(hash)
+    );
+    let client = chain.api.client();
+    let events: Option<String> = client.request("state_getStorage", params).await?;
+    match events {
+      Some(events) => self.decode_events(&hex::decode(events.trim_start_matches("0x"))?),
+      None => Ok(Vec::new()),
+    }
+  }
+
+  /// Decode the SCALE-encoded contents of the `System.Events` storage
+  pub fn decode_events(&self, bytes: &[u8]) -> Result<Vec<EventRecord>> {
+    let ty = self
+      .plain_storage_type("System", "Events")
+      .context("the runtime has no System.Events storage")?;
+    let records = self.decode_value(bytes, ty).context("malformed events")?;
+    match &records.value {
+      ValueDef::Composite(records) => records
+        .values()
+        .map(|record| event_record(record).context("malformed event record"))
+        .collect(),
+      _ => bail!("malformed events"),
+    }
+  }
+
+  /// Decode a value of type `ty`, rendering account IDs as SS58 addresses and byte arrays as
+  /// hexadecimal strings
+  pub fn decode_value(&self, bytes: &[u8], ty: u32) -> Result<Value<()>> {
+    self.decode_from(&mut &bytes[..], ty)
+  }
+
+  /// Decode a value of type `ty` from the start of `input`, advancing `input` past it
+  pub fn decode_from(&self, input: &mut &[u8], ty: u32) -> Result<Value<()>> {
+    let value = scale_value::scale::decode_as_type(input, ty, &self.runtime.types)
+      .map_err(|e| anyhow!("{e}"))?;
+    Ok(self.humanize(value))
+  }
+
+  /// The type of parameter `name` of the runtime's extrinsic type, e.g. `Call`
+  pub fn extrinsic_param(&self, name: &str) -> Option<u32> {
+    let extrinsic = self.runtime.types.resolve(self.runtime.extrinsic.ty.id)?;
+    let param = extrinsic
+      .type_params
+      .iter()
+      .find(|param| param.name == name)?;
+    Some(param.ty.as_ref()?.id)
+  }
+
+  /// The prefix of the storage keys of the entries of map `pallet.entry` whose first key is the
+  /// SCALE-encoded `key`, e.g. of the `Identity.DidKeys` entries of one DID
+  pub fn map_prefix(&self, pallet: &str, entry: &str, key: &[u8]) -> Option<Vec<u8>> {
+    let storage = self
+      .runtime
+      .pallets
+      .iter()
+      .find(|p| p.name == pallet)?
+      .storage
+      .as_ref()?;
+    let hasher = match &storage.entries.iter().find(|e| e.name == entry)?.ty {
+      StorageEntryType::Map { hashers, .. } => hashers.first()?,
+      StorageEntryType::Plain(_) => return None,
+    };
+    let hashed = match hasher {
+      StorageHasher::Blake2_128 => blake2_128(key).to_vec(),
+      StorageHasher::Blake2_256 => blake2_256(key).to_vec(),
+      StorageHasher::Blake2_128Concat => [&blake2_128(key)[..], key].concat(),
+      StorageHasher::Twox128 => twox_128(key).to_vec(),
+      StorageHasher::Twox256 => twox_256(key).to_vec(),
+      StorageHasher::Twox64Concat => [&twox_64(key)[..], key].concat(),
+      StorageHasher::Identity => key.to_vec(),
+    };
+    Some(
+      [
+        &twox_128(storage.prefix.as_bytes())[..],
+        &twox_128(entry.as_bytes()),
+        &hashed,
+      ]
+      .concat(),
+    )
+  }
+
+  fn plain_storage_type(&self, pallet: &str, entry: &str) -> Option<u32> {
+    let pallet = self.runtime.pallets.iter().find(|p| p.name == pallet)?;
+    let entry = pallet
+      .storage
+      .as_ref()?
+      .entries
+      .iter()
+      .find(|e| e.name == entry)?;
+    match &entry.ty {
+      StorageEntryType::Plain(ty) => Some(ty.id),
+      _ => None,
+    }
+  }
+
+  fn humanize(&self, value: Value<u32>) -> Value<()> {
+    if let Some(bytes) = self.bytes(&value) {
+      let rendered = match <[u8; 32]>::try_from(bytes.as_slice()) {
+        Ok(account) if self.is_account_id(value.context) => AccountId::from(account)
+          .to_ss58check_with_version(Ss58AddressFormat::custom(self.ss58_format)),
+        _ => String::from("0x") + &hex::encode(bytes),
+      };
+      return Value::string(rendered);
+    }
+    let ty = self
+      .runtime
+      .types
+      .resolve(value.context)
+      .map(|ty| &ty.type_def);
+    let value = match value.value {
+      ValueDef::Composite(composite) => {
+        let fields = match ty {
+          Some(TypeDef::Composite(def)) => def.fields.as_slice(),
+          _ => &[],
+        };
+        ValueDef::Composite(self.humanize_composite(composite, fields))
+      }
+      ValueDef::Variant(variant) => {
+        let fields = match ty {
+          Some(TypeDef::Variant(def)) => def
+            .variants
+            .iter()
+            .find(|v| v.name == variant.name)
+            .map_or(&[][..], |v| v.fields.as_slice()),
+          _ => &[],
+        };
+        ValueDef::Variant(Variant {
+          name: variant.name,
+          values: self.humanize_composite(variant.values, fields),
+        })
+      }
+      other => other.map_context(|_| ()),
+    };
+    Value { value, context: () }
+  }
+
+  /// Humanize the values of a composite or variant whose fields are `fields`
+  fn humanize_composite(
+    &self,
+    composite: Composite<u32>,
+    fields: &[Field<PortableForm>],
+  ) -> Composite<()> {
+    let type_name = |i: usize| fields.get(i).and_then(|field| field.type_name.as_deref());
+    match composite {
+      Composite::Named(values) => Composite::Named(
+        values
+          .into_iter()
+          .enumerate()
+          .map(|(i, (name, value))| (name, self.humanize_field(value, type_name(i))))
+          .collect(),
+      ),
+      Composite::Unnamed(values) => Composite::Unnamed(
+        values
+          .into_iter()
+          .enumerate()
+          .map(|(i, value)| self.humanize_field(value, type_name(i)))
+          .collect(),
+      ),
+    }
+  }
+
+  /// Humanize a field, rendering it as an amount if its type is named as a balance, e.g.
+  /// `T::Balance`, and a balance symbol is set
+  fn humanize_field(&self, value: Value<u32>, type_name: Option<&str>) -> Value<()> {
+    match (&self.balance_symbol, type_name, &value.value) {
+      (Some(symbol), Some(name), ValueDef::Primitive(Primitive::U128(amount)))
+        if name.contains("Balance") =>
+      {
+        Value::string(Balance::from_micro(*amount).with_symbol(symbol))
+      }
+      _ => self.humanize(value),
+    }
+  }
+
+  fn is_account_id(&self, ty: u32) -> bool {
+    let path = self.runtime.types.resolve(ty).map(|ty| &ty.path.segments);
+    path
+      .and_then(|segments| segments.last())
+      .map(String::as_str)
+      == Some("AccountId32")
+  }
+
+  /// The bytes of a value of a byte array or byte vector type, or of a type wrapping one
+  fn bytes(&self, value: &Value<u32>) -> Option<Vec<u8>> {
+    let types = &self.runtime.types;
+    let is_u8 = |ty: u32| {
+      matches!(
+        types.resolve(ty).map(|ty| &ty.type_def),
+        Some(TypeDef::Primitive(TypeDefPrimitive::U8))
+      )
+    };
+    let values = match (&types.resolve(value.context)?.type_def, &value.value) {
+      (TypeDef::Array(def), ValueDef::Composite(values)) if is_u8(def.type_param.id) => values,
+      (TypeDef::Sequence(def), ValueDef::Composite(values)) if is_u8(def.type_param.id) => values,
+      (TypeDef::Composite(_), ValueDef::Composite(values)) if values.len() == 1 => {
+        return self.bytes(values.values().next()?);
+      }
+      _ => return None,
+    };
+    values
+      .values()
+      .map(|value| match value.value {
+        ValueDef::Primitive(Primitive::U128(byte)) => u8::try_from(byte).ok(),
+        _ => None,
+      })
+      .collect()
+  }
+}
+
+/// Extract the extrinsic index and event of an event record
+fn event_record(record: &Value) -> Option<EventRecord> {
+  let extrinsic_index = match &record.at("phase")?.value {
+    ValueDef::Variant(phase) if phase.name == "ApplyExtrinsic" => {
+      Some(phase.values.at(0)?.as_u128()? as u32)
+    }
+    _ => None,
+  };
+  let (pallet, event) = nested_variant(record.at("event")?)?;
+  Some(EventRecord {
+    extrinsic_index,
+    event: EventReport {
+      pallet: pallet.name,
+      name: event.name,
+      fields: event.values,
+    },
+  })
+}
+
+/// Split a call or event into its pallet and inner variants, as they are nested in a variant per
+/// pallet, e.g. `Balances(Transfer { .. })`
+fn nested_variant(value: &Value) -> Option<(Variant<()>, Variant<()>)> {
+  match &value.value {
+    ValueDef::Variant(pallet) => match &pallet.values.at(0)?.value {
+      ValueDef::Variant(inner) => Some((pallet.clone(), inner.clone())),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::network::Network;
+
+  fn raw_record(phase: Value, pallet: &str, event: Value) -> Value {
+    Value::named_composite([
+      ("phase", phase),
+      ("event", Value::unnamed_variant(pallet, [event])),
+      ("topics", Value::unnamed_composite([])),
+    ])
+  }
+
+  #[test]
+  fn it_extracts_event_records() {
+    let transfer = Value::named_variant("Transfer", [("amount", Value::u128(5))]);
+    let phase = Value::unnamed_variant("ApplyExtrinsic", [Value::u128(2)]);
+    let record = event_record(&raw_record(phase, "Balances", transfer)).unwrap();
+    assert_eq!(record.extrinsic_index, Some(2));
+    assert_eq!(record.event.to_string(), "Balances.Transfer { amount: 5 }");
+
+    let phase = Value::unnamed_variant("Finalization", []);
+    let event = Value::unnamed_variant("NewSession", []);
+    let record = event_record(&raw_record(phase, "Session", event)).unwrap();
+    assert_eq!(record.extrinsic_index, None);
+    assert_eq!(record.event.to_string(), "Session.NewSession");
+  }
+
+  #[tokio::test]
+  async fn it_names_pallet_errors_on_testnet() {
+    let chain = ChainContext::connect(&Network::testnet()).await.unwrap();
+    let metadata = Metadata::fetch(&chain, None).await.unwrap();
+    let balances = metadata
+      .runtime
+      .pallets
+      .iter()
+      .find(|p| p.name == "Balances")
+      .unwrap();
+    let error = Value::unnamed_variant(
+      "Module",
+      [Value::named_composite([
+        ("index", Value::u128(balances.index as u128)),
+        ("error", Value::unnamed_composite((0..4).map(Value::u128))),
+      ])],
+    );
+    assert!(metadata
+      .dispatch_error(&error)
+      .name
+      .starts_with("Balances."));
+    let error = Value::unnamed_variant(
+      "Module",
+      [Value::named_composite([
+        ("index", Value::u128(balances.index as u128)),
+        ("error", Value::string("0x00000000")),
+      ])],
+    );
+    assert_eq!(
+      metadata.dispatch_error(&error),
+      metadata.module_error(balances.index, 0).unwrap()
+    );
+    let error = Value::unnamed_variant("BadOrigin", []);
+    assert_eq!(metadata.dispatch_error(&error).name, "BadOrigin");
+  }
+
+  #[tokio::test]
+  async fn it_computes_map_prefixes_on_testnet() {
+    let chain = ChainContext::connect(&Network::testnet()).await.unwrap();
+    let metadata = Metadata::fetch(&chain, None).await.unwrap();
+    let did = [0xab; 32];
+    let prefix = metadata.map_prefix("Identity", "DidKeys", &did).unwrap();
+    assert!(prefix.starts_with(&[twox_128(b"Identity"), twox_128(b"DidKeys")].concat()));
+    assert!(prefix.ends_with(&did));
+    assert!(metadata.map_prefix("System", "Number", &did).is_none());
+    assert!(metadata.map_prefix("Nope", "DidKeys", &did).is_none());
+  }
+}
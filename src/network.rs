@@ -0,0 +1,196 @@
+use crate::{
+  amount::DECIMALS,
+  output::{ErrorCode, Failure},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// If set, overrides the location of the config file
+const CONFIG_ENV: &str = "POLYMESH_CLI_CONFIG";
+/// Network used when neither `--network` nor the config file's `default` names one
+const DEFAULT_NETWORK: &str = "testnet";
+
+/// A named chain the CLI can connect to, along with how to format its addresses and amounts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Network {
+  /// Websocket URL of the node's RPC endpoint
+  pub url: String,
+  pub ss58_prefix: u16,
+  /// Decimal places of the native token, checked against the node's on connect
+  #[serde(default = "default_decimals")]
+  pub decimals: u8,
+  pub symbol: String,
+  /// If set, nothing is signed unless the node's genesis hash is this one
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genesis_hash: Option<String>,
+}
+
+impl Network {
+  pub fn mainnet() -> Self {
+    Self {
+      url: String::from("wss://mainnet-rpc.polymesh.network"),
+      ss58_prefix: 12,
+      decimals: default_decimals(),
+      symbol: String::from("POLYX"),
+      genesis_hash: None,
+    }
+  }
+
+  pub fn testnet() -> Self {
+    Self {
+      url: String::from("wss://testnet-rpc.polymesh.live"),
+      ss58_prefix: 42,
+      decimals: default_decimals(),
+      symbol: String::from("POLYX"),
+      genesis_hash: None,
+    }
+  }
+
+  /// A `polymesh --dev` node running on this machine
+  pub fn local() -> Self {
+    Self {
+      url: String::from("ws://127.0.0.1:9944"),
+      ..Self::testnet()
+    }
+  }
+
+  fn builtin(name: &str) -> Option<Self> {
+    match name {
+      "mainnet" => Some(Self::mainnet()),
+      "testnet" => Some(Self::testnet()),
+      "local" => Some(Self::local()),
+      _ => None,
+    }
+  }
+}
+
+fn default_decimals() -> u8 {
+  DECIMALS as u8
+}
+
+/// The config file, e.g.
+///
+/// ```toml
+/// default = "staging"
+///
+/// [networks.staging]
+/// url = "wss://staging.example.com"
+/// ss58_prefix = 42
+/// decimals = 6  # optional
+/// symbol = "POLYX"
+/// genesis_hash = "0x..."  # optional
+/// ```
+///
+/// Profiles named `mainnet`, `testnet` or `local` replace the built-in ones.
+#[derive(Deserialize, Default, Debug)]
+pub struct Config {
+  pub default: Option<String>,
+  #[serde(default)]
+  pub networks: BTreeMap<String, Network>,
+}
+
+impl Config {
+  /// Read the config file under the user's config directory, or an empty config if none exists
+  pub fn open() -> Result<Self> {
+    let path = config_path()?;
+    if !path.exists() {
+      return Ok(Self::default());
+    }
+    let contents = fs::read_to_string(&path)?;
+    toml::from_str(&contents).with_context(|| format!("malformed config at {}", path.display()))
+  }
+
+  /// Look up the network profile `name`, or else the configured default. If `url` is set, it
+  /// replaces the profile's URL.
+  pub fn network(&self, name: Option<&str>, url: Option<&str>) -> Result<Network> {
+    let name = name.or(self.default.as_deref()).unwrap_or(DEFAULT_NETWORK);
+    let mut network = self
+      .networks
+      .get(name)
+      .cloned()
+      .or_else(|| Network::builtin(name))
+      .ok_or_else(|| {
+        Failure::new(
+          ErrorCode::Config,
+          format!("unknown network {name}, expected mainnet, testnet, local or a configured one"),
+        )
+      })?;
+    if let Some(url) = url {
+      network.url = String::from(url);
+    }
+    Ok(network)
+  }
+}
+
+fn config_path() -> Result<PathBuf> {
+  if let Ok(path) = std::env::var(CONFIG_ENV) {
+    return Ok(PathBuf::from(path));
+  }
+  let config_dir = dirs::config_dir().context("could not find the user's config directory")?;
+  Ok(config_dir.join("polymesh-cli").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CONFIG: &str = r#"
+    default = "staging"
+
+    [networks.staging]
+    url = "wss://staging.example.com"
+    ss58_prefix = 12
+    decimals = 6
+    symbol = "sPOLYX"
+    genesis_hash = "0x1234"
+
+    [networks.testnet]
+    url = "wss://archive.example.com"
+    ss58_prefix = 42
+    symbol = "POLYX"
+  "#;
+
+  #[test]
+  fn it_defaults_to_testnet() {
+    let network = Config::default().network(None, None).unwrap();
+    assert_eq!(network, Network::testnet());
+  }
+
+  #[test]
+  fn it_reads_profiles_from_the_config_file() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    let staging = config.network(None, None).unwrap();
+    assert_eq!(staging.url, "wss://staging.example.com");
+    assert_eq!(staging.symbol, "sPOLYX");
+    assert_eq!(staging.genesis_hash.as_deref(), Some("0x1234"));
+    let testnet = config.network(Some("testnet"), None).unwrap();
+    assert_eq!(testnet.url, "wss://archive.example.com");
+    assert_eq!(
+      config.network(Some("mainnet"), None).unwrap(),
+      Network::mainnet()
+    );
+  }
+
+  #[test]
+  fn it_overrides_the_url() {
+    let network = Config::default()
+      .network(Some("mainnet"), Some("ws://10.0.0.1:9944"))
+      .unwrap();
+    assert_eq!(network.url, "ws://10.0.0.1:9944");
+    assert_eq!(network.ss58_prefix, 12);
+  }
+
+  #[test]
+  fn it_defaults_to_the_polyx_decimals() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    assert_eq!(config.network(None, None).unwrap().decimals, 6);
+    let testnet = config.network(Some("testnet"), None).unwrap();
+    assert_eq!(testnet.decimals, DECIMALS as u8);
+  }
+
+  #[test]
+  fn it_rejects_unknown_networks() {
+    assert!(Config::default().network(Some("devnet"), None).is_err());
+  }
+}
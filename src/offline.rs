@@ -0,0 +1,187 @@
+use crate::{
+  amount::Balance,
+  chain::ChainContext,
+  extrinsic::TxPayload,
+  metadata::Metadata,
+  network::Network,
+  output::{ErrorCode, Failure},
+  staking, transaction,
+  util::{self, SignerSpec, TxOptions, TxReceipt},
+};
+use anyhow::{Context, Result};
+use polymesh_api::{
+  client::{
+    sp_core::{crypto::Ss58Codec, hashing::blake2_256},
+    AccountId, Signer,
+  },
+  WrappedCall,
+};
+use serde::Serialize;
+use std::fmt;
+
+/// A call that can be built for offline signing
+#[derive(Clone, Debug)]
+pub enum OfflineCall {
+  Transfer { dest: String, amount: Balance },
+  Bond { controller: String, value: Balance },
+  Unbond { value: Balance },
+  BondExtra { value: Balance },
+  Nominate { validators: Vec<String> },
+  WithdrawUnbonded,
+}
+
+impl OfflineCall {
+  async fn call(&self, chain: &ChainContext, signer: AccountId) -> Result<WrappedCall> {
+    Ok(match self {
+      Self::Transfer { dest, amount } => {
+        transaction::transfer_call(chain, AccountId::from_ss58check(dest)?, *amount)?
+      }
+      Self::Bond { controller, value } => staking::bond_call(chain, controller, *value)?,
+      Self::Unbond { value } => chain.api.call().staking().unbond(value.micro())?,
+      Self::BondExtra { value } => chain.api.call().staking().bond_extra(value.micro())?,
+      Self::Nominate { validators } => {
+        let validators: Vec<&str> = validators.iter().map(|s| s.as_str()).collect();
+        staking::nominate_call(chain, &validators)?
+      }
+      Self::WithdrawUnbonded => staking::withdraw_unbonded_call(chain, signer).await?,
+    })
+  }
+}
+
+/// A transaction signed offline, ready to be submitted with `tx submit`
+#[derive(Serialize, Clone, Debug)]
+pub struct SignedTx {
+  /// Transaction hash as a hexadecimal string with an `0x` prefix
+  pub hash: String,
+  pub signer: String,
+  /// The payload's description of the call, which is not checked against the call signed
+  pub description: String,
+  /// SCALE-encoded call that was signed, as a hexadecimal string with an `0x` prefix
+  pub call: String,
+  /// Blake2-256 hash of the call, to compare to the one `tx build` showed
+  pub call_hash: String,
+  /// SCALE-encoded extrinsic as a hexadecimal string with an `0x` prefix
+  pub extrinsic: String,
+}
+
+impl fmt::Display for SignedTx {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "call: {} (unverified)", self.description)?;
+    writeln!(f, "call data: {}", self.call)?;
+    writeln!(f, "call hash: {}", self.call_hash)?;
+    write!(f, "extrinsic: {}", self.extrinsic)
+  }
+}
+
+/// Build `call` for the account with SS58 address `signer` to sign offline, with the nonce,
+/// mortality and tip set in `tx`. Immortal transactions can be signed at any time, but mortal ones
+/// must be signed and submitted before their era ends.
+pub async fn build(
+  call: &OfflineCall,
+  signer: &str,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxPayload> {
+  let signer = AccountId::from_ss58check(signer)
+    .map_err(|_| Failure::new(ErrorCode::InvalidInput, format!("invalid signer {signer}")))?;
+  let chain = ChainContext::connect(network).await?;
+  chain.ensure_expected_chain()?;
+  let call = call.call(&chain, signer).await?;
+  let tx = util::unsigned_tx(&chain, &call, signer, tx).await?;
+  let mut metadata = Metadata::fetch(&chain, None).await?;
+  metadata.render_balances(&chain.symbol);
+  let description = metadata.decode_call(&tx.call)?.to_string();
+  Ok(TxPayload::new(&tx, &chain, description))
+}
+
+/// Read an unsigned transaction from a payload file written by `tx build`
+pub fn read_payload(path: &str) -> Result<TxPayload> {
+  let contents =
+    std::fs::read_to_string(path).with_context(|| format!("could not read payload {path}"))?;
+  serde_json::from_str(&contents).map_err(|e| {
+    let message = format!("{path} is not a transaction payload: {e}");
+    Failure::new(ErrorCode::InvalidInput, message).into()
+  })
+}
+
+/// Sign an unsigned transaction with a local key, without connecting to a node
+pub async fn sign(payload: &TxPayload, signer: &SignerSpec) -> Result<SignedTx> {
+  let tx = payload.unsigned()?;
+  let signer = util::signer(signer)?;
+  if signer.account() != tx.signer {
+    let message = format!("the transaction must be signed by {}", payload.signer);
+    return Err(Failure::new(ErrorCode::InvalidInput, message).into());
+  }
+  let extrinsic = tx.sign(&signer).await?;
+  Ok(SignedTx {
+    hash: String::from("0x") + &hex::encode(blake2_256(&extrinsic)),
+    signer: payload.signer.clone(),
+    description: payload.description.clone(),
+    call: String::from("0x") + &hex::encode(&tx.call),
+    call_hash: payload.call_hash()?,
+    extrinsic: String::from("0x") + &hex::encode(extrinsic),
+  })
+}
+
+/// Submit a hexadecimal signed extrinsic and wait for it as set in `tx`
+pub async fn submit(extrinsic: &str, network: &Network, tx: &TxOptions) -> Result<TxReceipt> {
+  let extrinsic = extrinsic.trim();
+  let extrinsic = hex::decode(extrinsic.strip_prefix("0x").unwrap_or(extrinsic)).map_err(|_| {
+    Failure::new(
+      ErrorCode::InvalidInput,
+      "the extrinsic is not a hexadecimal string",
+    )
+  })?;
+  let chain = ChainContext::connect(network).await?;
+  util::submit_and_watch(&chain, &extrinsic, tx).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::util::SignerSource;
+
+  const KEY: &str = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+
+  #[tokio::test]
+  async fn it_builds_and_signs_transfers_offline() {
+    let network = Network::testnet();
+    let spec = SignerSpec::from(SignerSource::PrivateKey(String::from(KEY)));
+    let signer = util::signer(&spec).unwrap().account().to_ss58check();
+    let call = OfflineCall::Transfer {
+      dest: String::from("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"),
+      amount: "1".parse().unwrap(),
+    };
+    let payload = build(&call, &signer, &network, &TxOptions::default())
+      .await
+      .unwrap();
+    assert!(payload.description.starts_with("Balances.transfer"));
+
+    // The payload survives a round trip through its file format
+    let json = serde_json::to_string_pretty(&payload).unwrap();
+    let payload: TxPayload = serde_json::from_str(&json).unwrap();
+    let signed = sign(&payload, &spec).await.unwrap();
+    assert!(signed.extrinsic.starts_with("0x"));
+    assert_eq!(signed.call, payload.call);
+    assert_eq!(signed.call_hash, payload.call_hash().unwrap());
+    assert!(payload.to_string().contains(&signed.call_hash));
+
+    let other = SignerSpec::from(SignerSource::PrivateKey("11".repeat(32)));
+    assert!(sign(&payload, &other).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn it_rejects_unknown_payload_versions() {
+    let network = Network::testnet();
+    let spec = SignerSpec::from(SignerSource::PrivateKey(String::from(KEY)));
+    let signer = util::signer(&spec).unwrap().account().to_ss58check();
+    let call = OfflineCall::Unbond {
+      value: "1".parse().unwrap(),
+    };
+    let mut payload = build(&call, &signer, &network, &TxOptions::default())
+      .await
+      .unwrap();
+    payload.version += 1;
+    assert!(sign(&payload, &spec).await.is_err());
+  }
+}
@@ -0,0 +1,190 @@
+use crate::{amount::AmountError, signing::VerifyError};
+use polymesh_api::client::{error::Error as ClientError, sp_core::crypto::PublicError};
+use serde::Serialize;
+use std::{error::Error, fmt, str::FromStr};
+
+/// How command results and errors are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+  #[default]
+  Text,
+  Json,
+  Yaml,
+}
+
+impl FromStr for Format {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Self> {
+    match s {
+      "text" => Ok(Self::Text),
+      "json" => Ok(Self::Json),
+      "yaml" => Ok(Self::Yaml),
+      _ => anyhow::bail!("unknown output format {s}, expected text, json or yaml"),
+    }
+  }
+}
+
+impl Format {
+  /// Print one of a stream of results, as a line of text or JSON, or as a YAML document
+  pub fn print_event<T: Serialize + fmt::Display>(self, event: &T) {
+    match self {
+      Self::Text => println!("{event}"),
+      Self::Json => println!(
+        "{}",
+        serde_json::to_string(event).expect("results serialize to JSON")
+      ),
+      Self::Yaml => print!(
+        "---\n{}",
+        serde_yaml::to_string(event).expect("results serialize to YAML")
+      ),
+    }
+  }
+}
+
+/// The result of a command, displayed as text or serialized as JSON or YAML
+pub trait Report: erased_serde::Serialize + fmt::Display {}
+
+impl<T: Serialize + fmt::Display> Report for T {}
+
+erased_serde::serialize_trait_object!(Report);
+
+/// Stable, machine-readable class of an error
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+  /// A malformed argument, e.g. an address, key, signature or amount
+  InvalidInput,
+  /// The config file couldn't be parsed, or names an unknown network
+  Config,
+  /// A keystore account or key file couldn't be found or decrypted
+  Keystore,
+  /// The node couldn't be reached, or rejected a query or transaction
+  Rpc,
+  /// The node isn't on the chain its network profile expects
+  WrongChain,
+  /// A transaction was included in a block but failed to dispatch
+  DispatchFailed,
+  Io,
+  Other,
+}
+
+/// An error carrying its code, for failures that can't be classified by their source
+#[derive(Debug)]
+pub struct Failure {
+  pub code: ErrorCode,
+  pub message: String,
+  /// Structured data about the failure, e.g. the receipt of a failed transaction
+  pub details: Option<serde_json::Value>,
+}
+
+impl Failure {
+  pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+      details: None,
+    }
+  }
+
+  pub fn with_details(self, details: &impl Serialize) -> Self {
+    Self {
+      details: serde_json::to_value(details).ok(),
+      ..self
+    }
+  }
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl Error for Failure {}
+
+/// An error as printed in JSON or YAML output
+#[derive(Serialize, Debug)]
+pub struct ErrorReport {
+  pub code: ErrorCode,
+  /// The error followed by its causes
+  pub message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub details: Option<serde_json::Value>,
+}
+
+impl From<&anyhow::Error> for ErrorReport {
+  fn from(error: &anyhow::Error) -> Self {
+    let details = error
+      .chain()
+      .find_map(|cause| cause.downcast_ref::<Failure>()?.details.clone());
+    Self {
+      code: error_code(error),
+      message: format!("{error:#}"),
+      details,
+    }
+  }
+}
+
+/// Classify an error by the first cause in its chain that has a known code
+fn error_code(error: &anyhow::Error) -> ErrorCode {
+  for cause in error.chain() {
+    if let Some(failure) = cause.downcast_ref::<Failure>() {
+      return failure.code;
+    }
+    if cause.is::<PublicError>()
+      || cause.is::<VerifyError>()
+      || cause.is::<AmountError>()
+      || cause.is::<hex::FromHexError>()
+      || cause.is::<bip39::Error>()
+      || cause.is::<serde_json::Error>()
+    {
+      return ErrorCode::InvalidInput;
+    }
+    if cause.is::<toml::de::Error>() {
+      return ErrorCode::Config;
+    }
+    if cause.is::<ClientError>() {
+      return ErrorCode::Rpc;
+    }
+    if cause.is::<std::io::Error>() {
+      return ErrorCode::Io;
+    }
+  }
+  ErrorCode::Other
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use anyhow::Context;
+
+  #[test]
+  fn it_classifies_errors_by_their_cause() {
+    let error = hex::decode("xyz").context("bad payload").unwrap_err();
+    let report = ErrorReport::from(&error);
+    assert_eq!(report.code, ErrorCode::InvalidInput);
+    assert!(report.message.starts_with("bad payload: "));
+
+    let error = anyhow::Error::from(Failure::new(ErrorCode::WrongChain, "wrong chain"));
+    assert_eq!(ErrorReport::from(&error).code, ErrorCode::WrongChain);
+    let failure = Failure::new(ErrorCode::DispatchFailed, "failed").with_details(&[1, 2]);
+    let report = ErrorReport::from(&anyhow::Error::from(failure));
+    assert_eq!(report.details, Some(serde_json::json!([1, 2])));
+    assert_eq!(
+      ErrorReport::from(&anyhow::anyhow!("?")).code,
+      ErrorCode::Other
+    );
+  }
+
+  #[test]
+  fn it_serializes_error_codes_in_snake_case() {
+    let report = ErrorReport {
+      code: ErrorCode::InvalidInput,
+      message: String::from("bad"),
+      details: None,
+    };
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(json, r#"{"code":"invalid_input","message":"bad"}"#);
+  }
+}
@@ -0,0 +1,415 @@
+use crate::{
+  identity,
+  metadata::Metadata,
+  output::{ErrorCode, Failure},
+};
+use anyhow::{Context, Result};
+use polymesh_api::{
+  client::IdentityId,
+  types::polymesh_primitives::{
+    identity_id::{PortfolioId, PortfolioKind, PortfolioNumber},
+    secondary_key::{PalletPermissions, Permissions},
+    subset::SubsetRestriction,
+    ticker::Ticker,
+    DispatchableName, PalletName,
+  },
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt, fs,
+};
+
+/// Longest ticker of an asset, in bytes
+const TICKER_LEN: usize = 12;
+
+/// Which items of one dimension of a secondary key's permissions, e.g. which assets, the key may
+/// use
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Restriction {
+  #[default]
+  Whole,
+  These(#[serde(deserialize_with = "items")] Vec<String>),
+  Except(#[serde(deserialize_with = "items")] Vec<String>),
+}
+
+/// Deserialize the items of a restriction, which may be given as numbers, e.g. portfolios
+fn items<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Item {
+    Name(String),
+    Number(u64),
+  }
+  let items = Vec::<Item>::deserialize(deserializer)?;
+  Ok(
+    items
+      .into_iter()
+      .map(|item| match item {
+        Item::Name(name) => name,
+        Item::Number(number) => number.to_string(),
+      })
+      .collect(),
+  )
+}
+
+impl Restriction {
+  /// Only the items `these`, or all items but `except`, or else all items
+  pub fn new(these: Option<Vec<String>>, except: Option<Vec<String>>) -> Self {
+    match (these, except) {
+      (Some(these), _) => Self::These(these),
+      (None, Some(except)) => Self::Except(except),
+      (None, None) => Self::Whole,
+    }
+  }
+
+  /// Convert each item with `item`, adding the errors of invalid items to `errors`
+  fn to_subset<T: Ord>(
+    &self,
+    item: impl Fn(&str) -> Result<T, String>,
+    errors: &mut Vec<String>,
+  ) -> SubsetRestriction<T> {
+    let mut set = |items: &[String]| {
+      items
+        .iter()
+        .filter_map(|s| item(s).map_err(|e| errors.push(e)).ok())
+        .collect::<BTreeSet<T>>()
+    };
+    match self {
+      Self::Whole => SubsetRestriction::Whole,
+      Self::These(items) => SubsetRestriction::These(set(items)),
+      Self::Except(items) => SubsetRestriction::Except(set(items)),
+    }
+  }
+
+  /// Describe an on-chain subset, with `items` naming each of its elements
+  fn from_subset<T: Ord>(subset: &SubsetRestriction<T>, items: impl Fn(&T) -> Vec<String>) -> Self {
+    let names = |set: &BTreeSet<T>| set.iter().flat_map(&items).collect();
+    match subset {
+      SubsetRestriction::Whole => Self::Whole,
+      SubsetRestriction::These(set) => Self::These(names(set)),
+      SubsetRestriction::Except(set) => Self::Except(names(set)),
+    }
+  }
+}
+
+impl fmt::Display for Restriction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Whole => write!(f, "whole"),
+      Self::These(items) if items.is_empty() => write!(f, "none"),
+      Self::These(items) => write!(f, "{}", items.join(", ")),
+      Self::Except(items) => write!(f, "all except {}", items.join(", ")),
+    }
+  }
+}
+
+/// The assets, portfolios and extrinsics a secondary key may use. Assets are given by ticker,
+/// portfolios as `default` or by number, and extrinsics as `Pallet:call`, or as `Pallet` or
+/// `Pallet:*` for all the calls of a pallet.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermissionSet {
+  // Restrictions are `whole`, or maps such as `{these: [..]}` rather than YAML tags
+  #[serde(with = "serde_yaml::with::singleton_map")]
+  pub assets: Restriction,
+  #[serde(with = "serde_yaml::with::singleton_map")]
+  pub portfolios: Restriction,
+  #[serde(with = "serde_yaml::with::singleton_map")]
+  pub extrinsics: Restriction,
+}
+
+impl fmt::Display for PermissionSet {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "assets: {}\nportfolios: {}\nextrinsics: {}",
+      self.assets, self.portfolios, self.extrinsics
+    )
+  }
+}
+
+impl PermissionSet {
+  /// Read a permission set from a JSON or YAML policy file
+  pub fn read(path: &str) -> Result<Self> {
+    let contents =
+      fs::read_to_string(path).with_context(|| format!("could not read policy {path}"))?;
+    // YAML is a superset of JSON
+    serde_yaml::from_str(&contents).map_err(|e| {
+      let message = format!("{path} is not a permission policy: {e}");
+      Failure::new(ErrorCode::InvalidInput, message).into()
+    })
+  }
+
+  /// Describe the on-chain permissions of a secondary key of identity `did`. Portfolios of other
+  /// identities are prefixed with their DID, and a pallet with only some calls excluded is shown
+  /// as `Pallet except call, ..`, which policies do not accept.
+  pub fn from_permissions(permissions: &Permissions, did: IdentityId) -> Self {
+    let asset = |ticker: &Ticker| {
+      let len = ticker.0.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+      String::from_utf8_lossy(&ticker.0[..len]).into_owned()
+    };
+    let portfolio = |id: &PortfolioId| {
+      let kind = match &id.kind {
+        PortfolioKind::Default => String::from("default"),
+        PortfolioKind::User(number) => number.0.to_string(),
+      };
+      if id.did == did {
+        kind
+      } else {
+        format!("{}/{kind}", identity::did_to_hex(&id.did))
+      }
+    };
+    let name = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    let pallet = |pallet: &PalletPermissions| {
+      let pallet_name = name(&pallet.pallet_name.0);
+      let calls = |calls: &BTreeSet<DispatchableName>| {
+        let calls: Vec<String> = calls.iter().map(|call| name(&call.0)).collect();
+        calls.join(", ")
+      };
+      match &pallet.dispatchable_names {
+        SubsetRestriction::Whole => vec![pallet_name],
+        SubsetRestriction::These(names) => names
+          .iter()
+          .map(|call| format!("{pallet_name}:{}", name(&call.0)))
+          .collect(),
+        SubsetRestriction::Except(names) => vec![format!("{pallet_name} except {}", calls(names))],
+      }
+    };
+    Self {
+      assets: Restriction::from_subset(&permissions.asset, |t| vec![asset(t)]),
+      portfolios: Restriction::from_subset(&permissions.portfolio, |p| vec![portfolio(p)]),
+      extrinsics: Restriction::from_subset(&permissions.extrinsic, pallet),
+    }
+  }
+
+  /// The on-chain permissions of a secondary key of identity `did`, checking extrinsics against
+  /// `metadata` and failing with every invalid item
+  pub fn to_permissions(&self, metadata: &Metadata, did: IdentityId) -> Result<Permissions> {
+    let mut errors = Vec::new();
+    let asset = self.assets.to_subset(ticker, &mut errors);
+    let portfolio = self
+      .portfolios
+      .to_subset(|s| portfolio(did, s), &mut errors);
+    let extrinsic = match &self.extrinsics {
+      Restriction::Whole => SubsetRestriction::Whole,
+      Restriction::These(items) => SubsetRestriction::These(pallets(metadata, items, &mut errors)),
+      Restriction::Except(items) => {
+        SubsetRestriction::Except(pallets(metadata, items, &mut errors))
+      }
+    };
+    if !errors.is_empty() {
+      return Err(Failure::new(ErrorCode::InvalidInput, errors.join("\n")).into());
+    }
+    Ok(Permissions {
+      asset,
+      extrinsic,
+      portfolio,
+    })
+  }
+}
+
+/// An asset's ticker, which is upper case and zero-padded on chain
+fn ticker(s: &str) -> Result<Ticker, String> {
+  if s.is_empty() || s.len() > TICKER_LEN {
+    return Err(format!(
+      "invalid ticker {s}: expected 1 to {TICKER_LEN} bytes"
+    ));
+  }
+  let mut bytes = [0; TICKER_LEN];
+  bytes[..s.len()].copy_from_slice(s.to_ascii_uppercase().as_bytes());
+  Ok(Ticker(bytes))
+}
+
+/// A portfolio of identity `did`: its default portfolio, or a numbered user portfolio
+fn portfolio(did: IdentityId, s: &str) -> Result<PortfolioId, String> {
+  let kind = match s {
+    "default" => PortfolioKind::Default,
+    _ => match s.parse() {
+      Ok(number) => PortfolioKind::User(PortfolioNumber(number)),
+      Err(_) => {
+        return Err(format!(
+          "invalid portfolio {s}: expected default or a number"
+        ))
+      }
+    },
+  };
+  Ok(PortfolioId { did, kind })
+}
+
+/// The permissions of each pallet named in `items`, which are `Pallet:call`, `Pallet` or `Pallet:*`.
+/// The calls of a pallet are merged, and naming a pallet on its own permits all of its calls.
+fn pallets(
+  metadata: &Metadata,
+  items: &[String],
+  errors: &mut Vec<String>,
+) -> BTreeSet<PalletPermissions> {
+  // Calls of each pallet, or `None` for all of them
+  let mut pallets: BTreeMap<&str, Option<BTreeSet<&str>>> = BTreeMap::new();
+  for item in items {
+    let (pallet, call) = match item.split_once(':') {
+      Some((pallet, "*")) => (pallet, None),
+      Some((pallet, call)) => (pallet, Some(call)),
+      None => (item.as_str(), None),
+    };
+    let calls = match metadata.call_names(pallet) {
+      Some(calls) => calls,
+      None => {
+        errors.push(format!("unknown pallet {pallet}"));
+        continue;
+      }
+    };
+    match call {
+      Some(call) if !calls.iter().any(|name| name == call) => {
+        errors.push(format!("unknown extrinsic {pallet}:{call}"));
+      }
+      Some(call) => {
+        if let Some(calls) = pallets
+          .entry(pallet)
+          .or_insert_with(|| Some(BTreeSet::new()))
+        {
+          calls.insert(call);
+        }
+      }
+      None => {
+        pallets.insert(pallet, None);
+      }
+    }
+  }
+  pallets
+    .into_iter()
+    .map(|(pallet, calls)| PalletPermissions {
+      pallet_name: PalletName(pallet.as_bytes().to_vec()),
+      dispatchable_names: match calls {
+        Some(calls) => SubsetRestriction::These(
+          calls
+            .into_iter()
+            .map(|call| DispatchableName(call.as_bytes().to_vec()))
+            .collect(),
+        ),
+        None => SubsetRestriction::Whole,
+      },
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{chain::ChainContext, network::Network};
+
+  #[test]
+  fn it_reads_json_and_yaml_policies() {
+    let yaml = "assets:\n  these: [ACME, FOO]\nextrinsics:\n  except: ['Staking']\n";
+    let policy: PermissionSet = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(
+      policy.assets,
+      Restriction::These(vec![String::from("ACME"), String::from("FOO")])
+    );
+    assert_eq!(policy.portfolios, Restriction::Whole);
+    assert_eq!(
+      policy.to_string(),
+      "assets: ACME, FOO\nportfolios: whole\nextrinsics: all except Staking"
+    );
+
+    let json = r#"{"portfolios": {"these": ["default", 2]}, "extrinsics": "whole"}"#;
+    let policy: PermissionSet = serde_yaml::from_str(json).unwrap();
+    assert_eq!(
+      policy.portfolios,
+      Restriction::new(Some(vec![String::from("default"), String::from("2")]), None)
+    );
+    assert!(serde_yaml::from_str::<PermissionSet>("asset: whole").is_err());
+  }
+
+  #[test]
+  fn it_pads_tickers_and_numbers_portfolios() {
+    assert_eq!(ticker("acme").unwrap().0, *b"ACME\0\0\0\0\0\0\0\0");
+    assert!(ticker("").is_err());
+    assert!(ticker("THIRTEEN_LONG").is_err());
+
+    let did = IdentityId([0xab; 32]);
+    assert!(matches!(
+      portfolio(did, "default").unwrap().kind,
+      PortfolioKind::Default
+    ));
+    assert!(matches!(
+      portfolio(did, "3").unwrap().kind,
+      PortfolioKind::User(PortfolioNumber(3))
+    ));
+    assert!(portfolio(did, "-1").is_err());
+  }
+
+  #[test]
+  fn it_describes_on_chain_permissions() {
+    let did = IdentityId([0xab; 32]);
+    let other = IdentityId([0xcd; 32]);
+    let call = |name: &str| DispatchableName(name.as_bytes().to_vec());
+    let permissions = Permissions {
+      asset: SubsetRestriction::These([ticker("ACME").unwrap()].into_iter().collect()),
+      portfolio: SubsetRestriction::Except(
+        [
+          portfolio(did, "default").unwrap(),
+          portfolio(other, "2").unwrap(),
+        ]
+        .into_iter()
+        .collect(),
+      ),
+      extrinsic: SubsetRestriction::These(
+        [
+          PalletPermissions {
+            pallet_name: PalletName(b"Balances".to_vec()),
+            dispatchable_names: SubsetRestriction::These([call("transfer")].into_iter().collect()),
+          },
+          PalletPermissions {
+            pallet_name: PalletName(b"Staking".to_vec()),
+            dispatchable_names: SubsetRestriction::Except([call("bond")].into_iter().collect()),
+          },
+        ]
+        .into_iter()
+        .collect(),
+      ),
+    };
+    let set = PermissionSet::from_permissions(&permissions, did);
+    assert_eq!(set.assets, Restriction::These(vec![String::from("ACME")]));
+    assert_eq!(
+      set.to_string(),
+      format!(
+        "assets: ACME\nportfolios: all except default, 0x{}/2\n\
+         extrinsics: Balances:transfer, Staking except bond",
+        "cd".repeat(32)
+      )
+    );
+  }
+
+  #[tokio::test]
+  async fn it_checks_extrinsics_against_metadata() {
+    let chain = ChainContext::connect(&Network::testnet()).await.unwrap();
+    let metadata = Metadata::fetch(&chain, None).await.unwrap();
+    let did = IdentityId([0xab; 32]);
+    let set = |extrinsics: &[&str]| PermissionSet {
+      extrinsics: Restriction::These(extrinsics.iter().map(|s| String::from(*s)).collect()),
+      ..Default::default()
+    };
+
+    let allowed = set(&[
+      "Balances:transfer",
+      "Balances:transfer_with_memo",
+      "Staking",
+    ]);
+    let permissions = allowed.to_permissions(&metadata, did).unwrap();
+    match permissions.extrinsic {
+      SubsetRestriction::These(pallets) => assert_eq!(pallets.len(), 2),
+      _ => panic!("expected a set of pallets"),
+    }
+
+    let error = set(&["Nope:transfer", "Balances:nope", "Balances:transfer"])
+      .to_permissions(&metadata, did)
+      .unwrap_err()
+      .to_string();
+    assert_eq!(
+      error,
+      "unknown pallet Nope\nunknown extrinsic Balances:nope"
+    );
+  }
+}
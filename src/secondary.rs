@@ -1,23 +1,44 @@
-use crate::util;
-use anyhow::{bail, Result};
+use crate::{
+  block::{self, AtBlock, BlockId},
+  chain::ChainContext,
+  identity,
+  metadata::Metadata,
+  network::Network,
+  output::{ErrorCode, Failure},
+  permissions::PermissionSet,
+  signing,
+  util::{self, AnySigner, Scheme, SignerSource, SignerSpec, TxOptions, TxReceipt},
+};
+use anyhow::{bail, Context, Result};
 use parity_scale_codec::{Decode, Encode};
 use polymesh_api::{
-  client::{sp_core::crypto::Ss58Codec, AccountId, IdentityId, Signer},
+  client::{
+    sp_core::crypto::{Ss58AddressFormat, Ss58Codec},
+    AccountId, IdentityId, Signer,
+  },
   types::{
     polymesh_common_utilities::traits::identity::SecondaryKeyWithAuth,
-    polymesh_primitives::{
-      secondary_key::{KeyRecord, Permissions, SecondaryKey},
-      subset::SubsetRestriction,
-    },
+    polymesh_primitives::secondary_key::{KeyRecord, SecondaryKey},
     primitive_types::H512,
   },
-  Api,
 };
-use std::time::{Duration, SystemTime};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+  collections::BTreeSet,
+  fmt, fs,
+  time::{Duration, SystemTime},
+};
 
 type Moment = u64;
 type AuthorizationNonce = u64;
 
+/// Type of the files written by `secondary auth-request`
+const AUTH_REQUEST_TYPE: &str = "polymesh-secondary-key-auth-request";
+/// Type of the files written by `secondary auth-sign`
+const AUTH_SIGNATURE_TYPE: &str = "polymesh-secondary-key-auth-signature";
+/// Version of the formats of authorization requests and signatures
+pub const AUTH_VERSION: u32 = 1;
+
 /// TargetIdAuthorization for adding a secondary key to a DID
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
 struct TargetIdAuthorization {
@@ -28,99 +49,744 @@ struct TargetIdAuthorization {
   pub expires_at: Moment,
 }
 
+/// The identity of which `primary_account` is the primary key, which alone may manage the
+/// identity's secondary keys
+async fn primary_did(chain: &ChainContext, primary_account: &AccountId) -> Result<IdentityId> {
+  match chain
+    .api
+    .query()
+    .identity()
+    .key_records(*primary_account)
+    .await?
+  {
+    Some(KeyRecord::PrimaryKey(did)) => Ok(did),
+    Some(_) => bail!("must use primary key to manage secondary keys"),
+    None => bail!(
+      "{} doesn't have an identity",
+      chain.ss58check(primary_account)
+    ),
+  }
+}
+
 async fn add_secondary_auth(
-  api: &Api,
+  chain: &ChainContext,
   primary_account: &AccountId,
   expires_at: u64,
-) -> Result<Vec<u8>> {
-  let identity_query = api.query().identity();
+) -> Result<TargetIdAuthorization> {
   // Create TargetIdAuthorization from target DID, the DID's nonce, and an expiry
-  let target_id = match identity_query.key_records(*primary_account).await? {
-    Some(KeyRecord::PrimaryKey(did)) => did,
-    Some(_) => bail!("must use primary key to add secondary keys"),
-    None => bail!("{:?} doesn't have an identity", *primary_account),
-  };
-  let nonce = identity_query
+  let target_id = primary_did(chain, primary_account).await?;
+  let nonce = chain
+    .api
+    .query()
+    .identity()
     .off_chain_authorization_nonce(target_id)
     .await?;
-  let auth = TargetIdAuthorization {
+  Ok(TargetIdAuthorization {
     target_id,
     nonce,
     expires_at,
-  };
-  Ok(auth.encode())
+  })
 }
 
-pub async fn add(
-  primary_mnemonic: &str, // private key of primary
-  secondary_key: &str,    // private key of secondary
-  expires_after: u64,     // authorization after this many seconds
-  mainnet: bool,          // On mainnet (as opposed to testnet)?
-) -> Result<String> {
-  // Get PairSigners for primary and secondary keys
-  let mut primary_signer = util::pairsigner_from_mnemonic(primary_mnemonic, None)?;
-  let secondary_signer = util::pairsigner_from_private_key(secondary_key)?;
-  let api = Api::new(util::url(mainnet)).await?;
-
-  // Create TargetIdAuthorization from target DID, the DID's nonce, and an expiry
-  let expires_at = SystemTime::now()
+/// Unix time in milliseconds `expires_after` seconds from now
+fn expiry(expires_after: u64) -> Moment {
+  SystemTime::now()
     .checked_add(Duration::from_secs(expires_after))
     .unwrap_or_else(SystemTime::now)
     .duration_since(SystemTime::UNIX_EPOCH)
     .expect("logic error in getting Unix time")
     .as_millis()
-    .min(u64::MAX as u128) as u64;
-  let auth_data = add_secondary_auth(&api, &primary_signer.account, expires_at).await?;
-  // After signing, the signature always comes back as 65 bytes (ECDSA signature)
-  let secondary_signature_65_bytes = secondary_signer.sign(&auth_data).await?.encode();
-  let secondary_signature: [u8; 64] = secondary_signature_65_bytes[1..].try_into()?;
-  let auth_signature = H512(secondary_signature);
-
-  // Create a SecondaryKeyWithAuth to be submitted on-chain
-  // https://docs.rs/polymesh-api/0.3.3/polymesh_api/polymesh/types/polymesh_primitives/subset/enum.SubsetRestriction.html
-  let permissions = Permissions {
-    asset: SubsetRestriction::Whole,
-    extrinsic: SubsetRestriction::Whole,
-    portfolio: SubsetRestriction::Whole,
-  };
-  let secondary_key = SecondaryKey {
-    key: secondary_signer.account(),
-    permissions,
-  };
-  let secondary_key_with_auth = SecondaryKeyWithAuth {
-    secondary_key,
-    auth_signature,
+    .min(u64::MAX as u128) as u64
+}
+
+/// Sign an encoded `TargetIdAuthorization` with a secondary key, which must be sr25519 or ed25519
+/// since identities only accept 64-byte signatures
+async fn sign_auth(signer: &AnySigner, auth_data: &[u8]) -> Result<[u8; 64]> {
+  if signer.scheme() == Scheme::Ecdsa {
+    let message = "secondary keys must be sr25519 or ed25519 to sign their authorization";
+    return Err(Failure::new(ErrorCode::InvalidInput, message).into());
+  }
+  // Encoded signatures are the signature's scheme followed by its 64 bytes
+  let signature = signer.sign(auth_data).await?.encode();
+  Ok(signature[1..].try_into()?)
+}
+
+/// A request for a secondary key to authorize being added to an identity, written by `secondary
+/// auth-request` for the holder of the secondary key to sign with `secondary auth-sign`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthRequest {
+  /// Type of the file, which is `polymesh-secondary-key-auth-request`
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Version of the file format, which is [`AUTH_VERSION`]
+  pub version: u32,
+  pub chain: String,
+  pub genesis_hash: String,
+  /// SS58 address of the identity's primary key
+  pub primary: String,
+  pub did: String,
+  /// Off-chain authorization nonce of the identity
+  pub nonce: AuthorizationNonce,
+  /// Unix time in milliseconds when the authorization expires
+  pub expires_at: Moment,
+  /// SCALE-encoded `TargetIdAuthorization` to sign, as a hexadecimal string with an `0x` prefix
+  pub payload: String,
+}
+
+impl fmt::Display for AuthRequest {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "chain: {} ({})", self.chain, self.genesis_hash)?;
+    writeln!(f, "primary: {}", self.primary)?;
+    writeln!(f, "did: {}", self.did)?;
+    writeln!(f, "nonce: {}", self.nonce)?;
+    writeln!(f, "expires: {}", block::format_timestamp(self.expires_at))?;
+    write!(f, "payload: {}", self.payload)
+  }
+}
+
+impl AuthRequest {
+  fn new(chain: &ChainContext, primary: &AccountId, auth: &TargetIdAuthorization) -> Self {
+    Self {
+      kind: String::from(AUTH_REQUEST_TYPE),
+      version: AUTH_VERSION,
+      chain: chain.name.clone(),
+      genesis_hash: String::from("0x") + &hex::encode(chain.genesis_hash),
+      primary: chain.ss58check(primary),
+      did: identity::did_to_hex(&auth.target_id),
+      nonce: auth.nonce,
+      expires_at: auth.expires_at,
+      payload: String::from("0x") + &hex::encode(auth.encode()),
+    }
+  }
+
+  /// The authorization to sign, if the request is of a supported version and its payload is the
+  /// authorization of its DID, nonce and expiry
+  fn authorization(&self) -> Result<TargetIdAuthorization> {
+    let invalid = |message: String| Failure::new(ErrorCode::InvalidInput, message);
+    if self.kind != AUTH_REQUEST_TYPE || self.version != AUTH_VERSION {
+      let message = format!(
+        "unsupported authorization request {} version {}, expected {AUTH_REQUEST_TYPE} version \
+        {AUTH_VERSION}",
+        self.kind, self.version
+      );
+      return Err(invalid(message).into());
+    }
+    let did = hex::decode(self.did.trim_start_matches("0x"))
+      .ok()
+      .and_then(|did| <[u8; 32]>::try_from(did).ok())
+      .ok_or_else(|| invalid(format!("invalid DID {}", self.did)))?;
+    let auth = TargetIdAuthorization {
+      target_id: IdentityId(did),
+      nonce: self.nonce,
+      expires_at: self.expires_at,
+    };
+    if self.payload != String::from("0x") + &hex::encode(auth.encode()) {
+      let message = String::from("the payload doesn't match the request's DID, nonce and expiry");
+      return Err(invalid(message).into());
+    }
+    Ok(auth)
+  }
+}
+
+/// A secondary key's signature of an authorization request, written by `secondary auth-sign` for
+/// the primary key to submit with `secondary add --signature`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthSignature {
+  /// Type of the file, which is `polymesh-secondary-key-auth-signature`
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Version of the file format, which is [`AUTH_VERSION`]
+  pub version: u32,
+  /// SS58 address of the secondary key
+  pub secondary: String,
+  /// 64-byte signature of the request's payload, as a hexadecimal string with an `0x` prefix
+  pub signature: String,
+  pub request: AuthRequest,
+}
+
+impl fmt::Display for AuthSignature {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "secondary: {}", self.secondary)?;
+    writeln!(f, "did: {}", self.request.did)?;
+    writeln!(
+      f,
+      "expires: {}",
+      block::format_timestamp(self.request.expires_at)
+    )?;
+    write!(f, "signature: {}", self.signature)
+  }
+}
+
+/// A secondary key that signed its authorization elsewhere, along with what it may do
+#[derive(Clone)]
+pub struct SignedSecondary {
+  pub signature: AuthSignature,
+  pub permissions: PermissionSet,
+}
+
+/// Read a JSON file of type `kind` written by `secondary auth-request` or `secondary auth-sign`
+fn read_auth_file<T: DeserializeOwned>(path: &str, kind: &str) -> Result<T> {
+  let contents = fs::read_to_string(path).with_context(|| format!("could not read {path}"))?;
+  let invalid = |message: String| Failure::new(ErrorCode::InvalidInput, message);
+  let value: serde_json::Value = serde_json::from_str(&contents)
+    .map_err(|e| invalid(format!("{path} is not a JSON file: {e}")))?;
+  if value["type"] != kind {
+    return Err(invalid(format!("{path} is not a {kind} file")).into());
+  }
+  serde_json::from_value(value).map_err(|e| invalid(format!("{path} is malformed: {e}")).into())
+}
+
+/// Read an authorization request written by `secondary auth-request`
+pub fn read_auth_request(path: &str) -> Result<AuthRequest> {
+  read_auth_file(path, AUTH_REQUEST_TYPE)
+}
+
+/// Read an authorization signature written by `secondary auth-sign`
+pub fn read_auth_signature(path: &str) -> Result<AuthSignature> {
+  read_auth_file(path, AUTH_SIGNATURE_TYPE)
+}
+
+/// Request an authorization for secondary keys to sign, for them to be added to the identity of
+/// `primary` before it expires in `expires_after` seconds. Only the primary key's address is
+/// needed, not its secret.
+pub async fn auth_request(
+  primary: &str,
+  expires_after: u64,
+  network: &Network,
+) -> Result<AuthRequest> {
+  let primary = AccountId::from_ss58check(primary).map_err(|_| {
+    Failure::new(
+      ErrorCode::InvalidInput,
+      format!("invalid primary address {primary}"),
+    )
+  })?;
+  let chain = ChainContext::connect(network).await?;
+  chain.ensure_expected_chain()?;
+  let auth = add_secondary_auth(&chain, &primary, expiry(expires_after)).await?;
+  Ok(AuthRequest::new(&chain, &primary, &auth))
+}
+
+/// Sign an authorization request with a secondary key, without connecting to a node
+pub async fn auth_sign(request: &AuthRequest, secondary: &SignerSpec) -> Result<AuthSignature> {
+  let auth = request.authorization()?;
+  if auth.expires_at < expiry(0) {
+    let message = format!(
+      "the request expired at {}",
+      block::format_timestamp(auth.expires_at)
+    );
+    return Err(Failure::new(ErrorCode::InvalidInput, message).into());
+  }
+  let signer = util::signer(secondary)?;
+  let signature = sign_auth(&signer, &auth.encode()).await?;
+  // Render the secondary's address in the primary's address format
+  let format = AccountId::from_ss58check_with_version(&request.primary)
+    .map(|(_, format)| format)
+    .unwrap_or_else(|_| Ss58AddressFormat::custom(42));
+  Ok(AuthSignature {
+    kind: String::from(AUTH_SIGNATURE_TYPE),
+    version: AUTH_VERSION,
+    secondary: signer.account().to_ss58check_with_version(format),
+    signature: String::from("0x") + &hex::encode(signature),
+    request: request.clone(),
+  })
+}
+
+/// A secondary key to add to an identity, along with what it may do
+#[derive(Clone)]
+pub struct NewSecondary {
+  pub signer: SignerSpec,
+  pub permissions: PermissionSet,
+}
+
+/// An entry of a secondaries file: the private key or keystore alias of a secondary, and its
+/// permissions if they differ from the default ones
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecondaryEntry {
+  key: Option<String>,
+  account: Option<String>,
+  permissions: Option<PermissionSet>,
+}
+
+/// Read secondary keys to add from a JSON or YAML file listing each one's `key` or `account`,
+/// and optionally its `permissions`, which otherwise default to `permissions`
+pub fn read_secondaries(path: &str, permissions: &PermissionSet) -> Result<Vec<NewSecondary>> {
+  let contents =
+    fs::read_to_string(path).with_context(|| format!("could not read secondaries {path}"))?;
+  parse_secondaries(&contents, permissions)
+    .map_err(|e| Failure::new(ErrorCode::InvalidInput, format!("{path}: {e}")).into())
+}
+
+fn parse_secondaries(
+  contents: &str,
+  permissions: &PermissionSet,
+) -> Result<Vec<NewSecondary>, String> {
+  let entries: Vec<SecondaryEntry> = serde_yaml::from_str(contents).map_err(|e| e.to_string())?;
+  entries
+    .into_iter()
+    .enumerate()
+    .map(|(i, entry)| {
+      let source = match (entry.key, entry.account) {
+        (Some(key), None) => SignerSource::PrivateKey(key),
+        (None, Some(alias)) => SignerSource::Account(alias),
+        _ => {
+          return Err(format!(
+            "secondary {} needs either a key or an account",
+            i + 1
+          ))
+        }
+      };
+      Ok(NewSecondary {
+        signer: source.into(),
+        permissions: entry.permissions.unwrap_or_else(|| permissions.clone()),
+      })
+    })
+    .collect()
+}
+
+/// Add secondary keys to the identity of `primary`, each with its own permissions, in one
+/// transaction. Every secondary signs the same authorization, which expires along with the
+/// transaction: after `expires_after` seconds, or else when the authorization signed elsewhere by
+/// the `signed` secondaries expires, which must be the same for all of them.
+pub async fn add(
+  primary: &SignerSpec,         // signing account of primary
+  secondaries: &[NewSecondary], // signing accounts of secondaries and their permissions
+  signed: &[SignedSecondary],   // secondaries that signed elsewhere and their permissions
+  expires_after: Option<u64>,   // authorization after this many seconds
+  network: &Network,            // chain to submit the extrinsic to
+  tx: &TxOptions,               // how to submit the extrinsic
+) -> Result<TxReceipt> {
+  let invalid = |message: String| Failure::new(ErrorCode::InvalidInput, message);
+  if secondaries.is_empty() && signed.is_empty() {
+    return Err(invalid(String::from("there are no secondary keys to add")).into());
+  }
+  // Get PairSigners for primary and secondary keys
+  let mut primary_signer = util::signer(primary)?;
+  let secondary_signers = secondaries
+    .iter()
+    .map(|secondary| util::signer(&secondary.signer))
+    .collect::<Result<Vec<_>>>()?;
+  let signed_accounts = signed
+    .iter()
+    .map(|signed| {
+      AccountId::from_ss58check(&signed.signature.secondary)
+        .map_err(|_| invalid(format!("invalid secondary {}", signed.signature.secondary)))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut accounts = BTreeSet::new();
+  let all_accounts = secondary_signers.iter().map(|signer| signer.account());
+  for account in all_accounts.chain(signed_accounts.iter().copied()) {
+    if !accounts.insert(account) {
+      let message = format!(
+        "secondary {} is given more than once",
+        account.to_ss58check()
+      );
+      return Err(invalid(message).into());
+    }
+  }
+  let chain = ChainContext::connect(network).await?;
+
+  // Create TargetIdAuthorization from target DID, the DID's nonce, and an expiry
+  let expires_at = match (signed.first(), expires_after) {
+    (Some(first), _) => {
+      let expires_at = first.signature.request.expires_at;
+      if signed
+        .iter()
+        .any(|signed| signed.signature.request.expires_at != expires_at)
+      {
+        let message = String::from("the secondaries signed authorizations with different expiries");
+        return Err(invalid(message).into());
+      }
+      expires_at
+    }
+    (None, Some(expires_after)) => expiry(expires_after),
+    (None, None) => bail!("an expiry is required to add secondary keys"),
   };
+  let auth = add_secondary_auth(&chain, &primary_signer.account(), expires_at).await?;
+  let auth_data = auth.encode();
+  let payload = String::from("0x") + &hex::encode(&auth_data);
 
-  let additional_keys = vec![secondary_key_with_auth];
-  let call = api
+  // Create a SecondaryKeyWithAuth for each secondary to be submitted on-chain, with its
+  // permissions checked against the runtime's pallets and calls
+  let metadata = Metadata::fetch(&chain, None).await?;
+  let mut additional_keys = Vec::new();
+  for (secondary, signer) in secondaries.iter().zip(&secondary_signers) {
+    let secondary_key = SecondaryKey {
+      key: signer.account(),
+      permissions: secondary
+        .permissions
+        .to_permissions(&metadata, auth.target_id)?,
+    };
+    additional_keys.push(SecondaryKeyWithAuth {
+      secondary_key,
+      auth_signature: H512(sign_auth(signer, &auth_data).await?),
+    });
+  }
+  // Signatures made elsewhere must be of the identity's current authorization on this chain
+  for (signed, account) in signed.iter().zip(signed_accounts) {
+    let AuthSignature {
+      secondary,
+      signature,
+      request,
+      ..
+    } = &signed.signature;
+    if request.genesis_hash != String::from("0x") + &hex::encode(chain.genesis_hash) {
+      let message = format!(
+        "{secondary} signed for {}, not {}",
+        request.chain, chain.name
+      );
+      return Err(invalid(message).into());
+    }
+    if request.payload != payload {
+      let message = format!(
+        "{secondary} signed an outdated authorization or one for another identity; request a new \
+        one with secondary auth-request"
+      );
+      return Err(invalid(message).into());
+    }
+    let bytes = hex::decode(signature.trim_start_matches("0x"))
+      .ok()
+      .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+      .ok_or_else(|| invalid(format!("the signature of {secondary} isn't 64 bytes")))?;
+    signing::verify_signature(signature, secondary, &payload)
+      .with_context(|| format!("invalid signature of {secondary}"))?;
+    additional_keys.push(SecondaryKeyWithAuth {
+      secondary_key: SecondaryKey {
+        key: account,
+        permissions: signed
+          .permissions
+          .to_permissions(&metadata, auth.target_id)?,
+      },
+      auth_signature: H512(bytes),
+    });
+  }
+
+  let call = chain
+    .api
     .call()
     .identity()
     .add_secondary_keys_with_authorization(additional_keys, expires_at)?;
-  util::sign_submit_and_watch(&call, &mut primary_signer).await
+  util::sign_submit_and_watch(&chain, &call, &mut primary_signer, tx).await
 }
 
 /// Removes secondary key from account
-pub async fn remove(primary_mnemonic: &str, who: &str, mainnet: bool) -> Result<String> {
+pub async fn remove(
+  primary: &SignerSpec,
+  who: &str,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
   let who = AccountId::from_ss58check(who)?;
-  let call = Api::new(util::url(mainnet))
-    .await?
+  let chain = ChainContext::connect(network).await?;
+  let call = chain
+    .api
     .call()
     .identity()
     .remove_secondary_keys(vec![who])?;
-  let mut signer = util::pairsigner_from_mnemonic(primary_mnemonic, None)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+  let mut signer = util::signer(primary)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
+}
+
+/// A secondary key of an identity, along with what it may do
+#[derive(Serialize, Clone, Debug)]
+pub struct SecondaryKeyReport {
+  pub address: String,
+  pub permissions: PermissionSet,
+}
+
+/// The keys linked to an identity. Secondary keys are frozen and unfrozen all at once, so
+/// `frozen` applies to all of them.
+#[derive(Serialize, Clone, Debug)]
+pub struct KeyListReport {
+  pub did: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub primary: Option<String>,
+  pub frozen: bool,
+  pub secondaries: Vec<SecondaryKeyReport>,
+}
+
+impl fmt::Display for KeyListReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "did: {}", self.did)?;
+    if let Some(primary) = &self.primary {
+      write!(f, "\nprimary: {primary}")?;
+    }
+    if self.secondaries.is_empty() {
+      return write!(f, "\nno secondary keys");
+    }
+    let frozen = if self.frozen { " (frozen)" } else { "" };
+    for secondary in &self.secondaries {
+      write!(f, "\nsecondary: {}{frozen}", secondary.address)?;
+      for line in secondary.permissions.to_string().lines() {
+        write!(f, "\n  {line}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// A DID given as a hexadecimal string with an `0x` prefix
+fn parse_did(s: &str) -> Option<IdentityId> {
+  let did = hex::decode(s.strip_prefix("0x")?).ok()?;
+  Some(IdentityId(did.try_into().ok()?))
+}
+
+/// List the keys linked to the identity `who`, which is either a DID or the address of one of the
+/// identity's keys, with the permissions of each secondary key, at block `at` if set
+pub async fn list(
+  who: &str,
+  network: &Network,
+  at: Option<BlockId>,
+) -> Result<AtBlock<KeyListReport>> {
+  let (chain, block) = ChainContext::connect_at(network, at).await?;
+  let did = match parse_did(who) {
+    Some(did) => did,
+    None => {
+      let account = AccountId::from_ss58check(who).map_err(|_| {
+        let message = format!("{who} is neither a DID nor an address");
+        Failure::new(ErrorCode::InvalidInput, message)
+      })?;
+      identity::key_info(&chain, account).await?.did
+    }
+  };
+  // The storage layout of the runtime at that block, which an upgrade may have since changed
+  let metadata = Metadata::fetch(&chain, chain.block()).await?;
+  let prefix = metadata
+    .map_prefix("Identity", "DidKeys", &did.encode())
+    .context("the runtime has no Identity.DidKeys storage")?;
+  let identity_query = chain.query().identity();
+  let mut primary = None;
+  let mut secondaries = Vec::new();
+  for key in chain.storage_keys(&prefix).await? {
+    // Storage keys of `DidKeys` end with the account, which is hashed with `Twox64Concat`
+    let account = match key.len().checked_sub(32) {
+      Some(start) => AccountId::decode(&mut &key[start..])?,
+      None => bail!("malformed Identity.DidKeys storage key"),
+    };
+    match identity_query.key_records(account).await? {
+      Some(KeyRecord::PrimaryKey(_)) => primary = Some(chain.ss58check(&account)),
+      Some(KeyRecord::SecondaryKey(_, permissions)) => secondaries.push(SecondaryKeyReport {
+        address: chain.ss58check(&account),
+        permissions: PermissionSet::from_permissions(&permissions, did),
+      }),
+      _ => {}
+    }
+  }
+  if primary.is_none() && secondaries.is_empty() {
+    let message = format!("{} has no keys", identity::did_to_hex(&did));
+    return Err(Failure::new(ErrorCode::InvalidInput, message).into());
+  }
+  let report = KeyListReport {
+    did: identity::did_to_hex(&did),
+    primary,
+    frozen: identity_query.is_did_frozen(did).await?,
+    secondaries,
+  };
+  Ok(AtBlock { report, block })
+}
+
+/// Replace the permissions of the secondary key `who` of the identity of `primary`
+pub async fn set_permissions(
+  primary: &SignerSpec,
+  who: &str,
+  permissions: &PermissionSet,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let invalid = |message: String| Failure::new(ErrorCode::InvalidInput, message);
+  let who =
+    AccountId::from_ss58check(who).map_err(|_| invalid(format!("invalid secondary {who}")))?;
+  let mut signer = util::signer(primary)?;
+  let chain = ChainContext::connect(network).await?;
+  let did = primary_did(&chain, &signer.account()).await?;
+  match chain.api.query().identity().key_records(who).await? {
+    Some(KeyRecord::SecondaryKey(key_did, _)) if key_did == did => {}
+    _ => {
+      let message = format!(
+        "{} is not a secondary key of {}",
+        chain.ss58check(&who),
+        identity::did_to_hex(&did)
+      );
+      return Err(invalid(message).into());
+    }
+  }
+  let metadata = Metadata::fetch(&chain, None).await?;
+  let permissions = permissions.to_permissions(&metadata, did)?;
+  let call = chain
+    .api
+    .call()
+    .identity()
+    .set_secondary_key_permissions(who, permissions)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
+}
+
+/// Freeze all the secondary keys of the identity of `primary`, which then can't sign for the
+/// identity until they are unfrozen, or unfreeze them if `freeze` is not set
+pub async fn freeze(
+  primary: &SignerSpec,
+  freeze: bool,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let mut signer = util::signer(primary)?;
+  let chain = ChainContext::connect(network).await?;
+  primary_did(&chain, &signer.account()).await?;
+  let identity_call = chain.api.call().identity();
+  let call = if freeze {
+    identity_call.freeze_secondary_keys()?
+  } else {
+    identity_call.unfreeze_secondary_keys()?
+  };
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::permissions::Restriction;
+
+  #[test]
+  fn it_reads_secondaries_with_their_own_permissions() {
+    let contents = "- account: ops\n  permissions:\n    assets:\n      these: [ACME]\n\
+      - key: '6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e'\n";
+    let default = PermissionSet {
+      extrinsics: Restriction::These(vec![String::from("Balances")]),
+      ..Default::default()
+    };
+    let secondaries = parse_secondaries(contents, &default).unwrap();
+    assert_eq!(secondaries.len(), 2);
+    assert_eq!(
+      secondaries[0].signer.source,
+      SignerSource::Account(String::from("ops"))
+    );
+    assert_eq!(
+      secondaries[0].permissions.assets,
+      Restriction::These(vec![String::from("ACME")])
+    );
+    assert_eq!(secondaries[0].permissions.extrinsics, Restriction::Whole);
+    assert_eq!(secondaries[1].permissions, default);
+
+    assert!(parse_secondaries("- permissions: {}\n", &default).is_err());
+    assert!(parse_secondaries("- key: '11'\n  account: ops\n", &default).is_err());
+  }
+
+  const KEY: &str = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+
+  fn sample_request(nonce: u64, expires_at: u64) -> AuthRequest {
+    let auth = TargetIdAuthorization {
+      target_id: IdentityId([0xab; 32]),
+      nonce,
+      expires_at,
+    };
+    AuthRequest {
+      kind: String::from(AUTH_REQUEST_TYPE),
+      version: AUTH_VERSION,
+      chain: String::from("Polymesh Testnet"),
+      genesis_hash: String::from("0x") + &"00".repeat(32),
+      primary: String::from("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"),
+      did: identity::did_to_hex(&auth.target_id),
+      nonce,
+      expires_at,
+      payload: String::from("0x") + &hex::encode(auth.encode()),
+    }
+  }
+
+  #[tokio::test]
+  async fn it_signs_authorization_requests_offline() {
+    let key = SignerSpec::from(SignerSource::PrivateKey(String::from(KEY)));
+    let request = sample_request(7, expiry(3600));
+    let signed = auth_sign(&request, &key).await.unwrap();
+    assert_eq!(
+      signed.secondary,
+      util::signer(&key).unwrap().account().to_ss58check()
+    );
+    assert_eq!(signed.signature.len(), 2 + 2 * 64);
+    assert!(
+      signing::verify_signature(&signed.signature, &signed.secondary, &request.payload).is_ok()
+    );
+
+    // The payload must be the authorization of the request's DID, nonce and expiry
+    let tampered = AuthRequest {
+      nonce: 8,
+      ..request.clone()
+    };
+    assert!(auth_sign(&tampered, &key).await.is_err());
+    assert!(auth_sign(&sample_request(7, 1), &key).await.is_err());
+    let ecdsa = SignerSpec {
+      scheme: Some(Scheme::Ecdsa),
+      ..key.clone()
+    };
+    assert!(auth_sign(&request, &ecdsa).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn it_reads_only_auth_files_of_their_type() {
+    let path = std::env::temp_dir().join(format!("polymesh-cli-auth-{}.json", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    let request = sample_request(7, expiry(3600));
+    fs::write(&path, serde_json::to_string_pretty(&request).unwrap()).unwrap();
+    assert_eq!(
+      read_auth_request(path_str).unwrap().payload,
+      request.payload
+    );
+    assert!(read_auth_signature(path_str).is_err());
+
+    let key = SignerSpec::from(SignerSource::PrivateKey(String::from(KEY)));
+    let signed = auth_sign(&request, &key).await.unwrap();
+    fs::write(&path, serde_json::to_string_pretty(&signed).unwrap()).unwrap();
+    assert_eq!(
+      read_auth_signature(path_str).unwrap().signature,
+      signed.signature
+    );
+    assert!(read_auth_request(path_str).is_err());
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn it_lists_keys_of_dids_or_addresses() {
+    let did = String::from("0x") + &"ab".repeat(32);
+    assert_eq!(parse_did(&did), Some(IdentityId([0xab; 32])));
+    assert_eq!(parse_did(&did[2..]), None);
+    assert_eq!(parse_did("0xabab"), None);
+    assert_eq!(
+      parse_did("5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt"),
+      None
+    );
+
+    let report = KeyListReport {
+      did,
+      primary: Some(String::from(
+        "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt",
+      )),
+      frozen: true,
+      secondaries: vec![SecondaryKeyReport {
+        address: String::from("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"),
+        permissions: PermissionSet::default(),
+      }],
+    };
+    assert_eq!(
+      report.to_string().lines().skip(2).collect::<Vec<_>>(),
+      [
+        "secondary: 5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY (frozen)",
+        "  assets: whole",
+        "  portfolios: whole",
+        "  extrinsics: whole",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn it_rejects_listing_unknown_identities() {
+    let network = Network::testnet();
+    let unknown = String::from("0x") + &"ff".repeat(32);
+    assert!(list(&unknown, &network, None).await.is_err());
+    assert!(list("not a key", &network, None).await.is_err());
+  }
 
   #[tokio::test]
   #[ignore]
   async fn it_creates_auth_data() {
-    let url = util::url(false); // testnet
-    let _api = Api::new(url).await.unwrap();
+    let _chain = ChainContext::connect(&Network::testnet()).await.unwrap();
     // let account_id = AccountId::from_slice(did);
     let _expires_at = SystemTime::now()
       .checked_add(Duration::from_secs(24 * 60 * 60 * 365))
@@ -1,74 +1,266 @@
-#![allow(unused_imports, unused_variables)]
-use crate::util;
+use crate::util::{self, SignerSpec};
 use anyhow::Result;
 use parity_scale_codec::Encode;
-use polymesh_api::client::{
-  sp_core::crypto::Ss58Codec,
-  sp_core::sr25519::{Public, Signature},
-  Signer,
+use polymesh_api::client::Signer;
+use serde::Serialize;
+use sp_core::{
+  crypto::{AccountId32, Ss58Codec},
+  ecdsa, ed25519, sr25519,
 };
+use sp_runtime::{traits::Verify, MultiSignature};
+use std::fmt;
 
-/// Using a private key (given as a 32-byte hexadecimal string without a `0x` prefix)
-/// and a payload (given as a hexadecimal string without a `0x` prefix), this function
+/// A signature over a payload
+#[derive(Serialize, Clone, Debug)]
+pub struct SignedPayload {
+  /// SCALE-encoded `MultiSignature` as a hexadecimal string
+  pub signature: String,
+}
+
+impl fmt::Display for SignedPayload {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.signature)
+  }
+}
+
+/// Whether a well-formed signature was made by its signer over its payload
+#[derive(Serialize, Clone, Debug)]
+pub struct Verification {
+  pub valid: bool,
+}
+
+impl fmt::Display for Verification {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.valid)
+  }
+}
+
+/// Reason a signature failed to verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+  /// The named input was not a valid hexadecimal string
+  BadHex(&'static str),
+  /// The signer's address was not a valid SS58 address
+  BadAddress(String),
+  /// The signature was neither 64 bytes (sr25519/ed25519), 65 bytes (ecdsa, or a
+  /// scheme-tagged sr25519/ed25519 signature) nor 66 bytes (a scheme-tagged ecdsa signature)
+  WrongLength(usize),
+  /// The scheme tag of an encoded `MultiSignature` doesn't match its length
+  SchemeMismatch { tag: u8, len: usize },
+  /// The signature is well-formed but wasn't made by the signer over the payload
+  InvalidSignature,
+}
+
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BadHex(input) => write!(f, "{input} is not a valid hexadecimal string"),
+      Self::BadAddress(addr) => write!(f, "{addr} is not a valid SS58 address"),
+      Self::WrongLength(len) => write!(
+        f,
+        "signature must be 64, 65 or 66 bytes long, got {len} bytes"
+      ),
+      Self::SchemeMismatch { tag, len } => write!(
+        f,
+        "signature scheme tag {tag} doesn't match a {len}-byte encoded signature"
+      ),
+      Self::InvalidSignature => write!(f, "signature is invalid for this address and payload"),
+    }
+  }
+}
+
+impl std::error::Error for VerifyError {}
+
+fn decode_hex(input: &str, name: &'static str) -> Result<Vec<u8>, VerifyError> {
+  hex::decode(input.strip_prefix("0x").unwrap_or(input)).map_err(|_| VerifyError::BadHex(name))
+}
+
+/// All the ways a signature of a given length can be read. Raw 64-byte signatures may be
+/// sr25519 or ed25519, and a 65-byte signature is either a scheme-tagged sr25519/ed25519
+/// signature (as emitted by [`sign_payload`]) or a raw ecdsa signature.
+fn candidate_signatures(signature: &[u8]) -> Result<Vec<MultiSignature>, VerifyError> {
+  let mut candidates = Vec::new();
+  match signature.len() {
+    64 => {
+      let raw: [u8; 64] = signature.try_into().expect("length checked above");
+      candidates.push(sr25519::Signature::from_raw(raw).into());
+      candidates.push(ed25519::Signature::from_raw(raw).into());
+    }
+    65 => {
+      let tagged: [u8; 64] = signature[1..].try_into().expect("length checked above");
+      match signature[0] {
+        0 => candidates.push(ed25519::Signature::from_raw(tagged).into()),
+        1 => candidates.push(sr25519::Signature::from_raw(tagged).into()),
+        _ => (),
+      }
+      let raw: [u8; 65] = signature.try_into().expect("length checked above");
+      candidates.push(ecdsa::Signature::from_raw(raw).into());
+    }
+    66 => match signature[0] {
+      2 => {
+        let tagged: [u8; 65] = signature[1..].try_into().expect("length checked above");
+        candidates.push(ecdsa::Signature::from_raw(tagged).into());
+      }
+      tag => return Err(VerifyError::SchemeMismatch { tag, len: 66 }),
+    },
+    len => return Err(VerifyError::WrongLength(len)),
+  }
+  Ok(candidates)
+}
+
+/// Using a signing account (e.g. a private key given as a 32-byte hexadecimal string without a
+/// `0x` prefix) and a payload (given as a hexadecimal string without a `0x` prefix), this function
 /// uses the Polymesh API to sign the payload, yielding a signature which can be
 /// validated against the user's public address (a base-64 encoded address starting with 5).
-pub async fn sign_payload(signing_key: &str, payload: &str) -> Result<String> {
+pub async fn sign_payload(signer: &SignerSpec, payload: &str) -> Result<SignedPayload> {
   let payload = hex::decode(payload.strip_prefix("0x").unwrap_or(payload))?;
-  let signer = util::pairsigner_from_private_key(signing_key)?;
+  let signer = util::signer(signer)?;
   let res = signer.sign(&payload).await?;
-  Ok(hex::encode(res.encode()))
+  Ok(SignedPayload {
+    signature: hex::encode(res.encode()),
+  })
 }
 
-/// Verify a signature against a payload and its signer's public address
-pub fn verify_signature(signature: &str, ss58_addr: &str, payload: &str) -> bool {
-  let signature =
-    hex::decode(signature.strip_prefix("0x").unwrap_or(signature)).unwrap_or_default();
-  let payload = hex::decode(payload.strip_prefix("0x").unwrap_or(payload)).unwrap_or_default();
-  todo!()
-  // match (
-  //   Signature::from_slice(&signature),
-  //   Public::from_ss58check(ss58_addr),
-  // ) {
-  //   (Some(sig), Ok(public)) => public. sig.verify(&payload[..], &public),
-  //   _ => false,
-  // }
+/// Verify a signature against a payload and its signer's public address. The signature may be a
+/// SCALE-encoded `MultiSignature` or a raw sr25519, ed25519 or ecdsa signature.
+pub fn verify_signature(
+  signature: &str,
+  ss58_addr: &str,
+  payload: &str,
+) -> Result<(), VerifyError> {
+  let signature = decode_hex(signature, "signature")?;
+  let payload = decode_hex(payload, "payload")?;
+  let signer = AccountId32::from_ss58check(ss58_addr)
+    .map_err(|_| VerifyError::BadAddress(ss58_addr.to_string()))?;
+  if candidate_signatures(&signature)?
+    .iter()
+    .any(|sig| sig.verify(&payload[..], &signer))
+  {
+    Ok(())
+  } else {
+    Err(VerifyError::InvalidSignature)
+  }
+}
+
+/// Verify a signature as [`verify_signature`] does, reporting a signature that doesn't match as
+/// invalid rather than as an error
+pub fn verify(signature: &str, ss58_addr: &str, payload: &str) -> Result<Verification> {
+  match verify_signature(signature, ss58_addr, payload) {
+    Ok(()) => Ok(Verification { valid: true }),
+    Err(VerifyError::InvalidSignature) => Ok(Verification { valid: false }),
+    Err(e) => Err(e.into()),
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::util::SignerSource;
 
   #[tokio::test]
   async fn it_signs_a_payload() {
-    let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
     let payload = "deadbeef";
-    let signature = sign_payload(signing_addr, payload).await;
+    let signature = sign_payload(&signing_addr, payload).await;
     assert!(signature.is_ok());
   }
 
   #[tokio::test]
   async fn it_verifies_a_signature() {
-    let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
+    let ss58_addr = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
+    let payload = "deadbeef";
+    let signature = sign_payload(&signing_addr, payload)
+      .await
+      .unwrap()
+      .signature;
+    let res = verify_signature(&signature, ss58_addr, payload);
+    assert_eq!(res, Ok(()));
+  }
+
+  #[tokio::test]
+  async fn it_verifies_a_raw_sr25519_signature() {
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
     let ss58_addr = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
     let payload = "deadbeef";
-    let signature = sign_payload(signing_addr, payload).await.unwrap();
-    let is_valid = verify_signature(&signature, ss58_addr, payload);
-    assert!(is_valid);
+    let signature = sign_payload(&signing_addr, payload)
+      .await
+      .unwrap()
+      .signature;
+    let raw_signature = &signature[2..]; // strip the scheme tag
+    let res = verify_signature(raw_signature, ss58_addr, payload);
+    assert_eq!(res, Ok(()));
+  }
+
+  #[test]
+  fn it_verifies_ed25519_and_ecdsa_signatures() {
+    use sp_core::Pair;
+    use sp_runtime::{traits::IdentifyAccount, MultiSigner};
+    let payload = [0xde, 0xad, 0xbe, 0xef];
+
+    let pair = ed25519::Pair::from_seed(&[1; 32]);
+    let ss58_addr = AccountId32::from(pair.public()).to_ss58check();
+    let raw = hex::encode(pair.sign(&payload).0);
+    let tagged = hex::encode(MultiSignature::from(pair.sign(&payload)).encode());
+    assert_eq!(verify_signature(&raw, &ss58_addr, "deadbeef"), Ok(()));
+    assert_eq!(verify_signature(&tagged, &ss58_addr, "deadbeef"), Ok(()));
+
+    let pair = ecdsa::Pair::from_seed(&[2; 32]);
+    let ss58_addr = MultiSigner::from(pair.public())
+      .into_account()
+      .to_ss58check();
+    let raw = hex::encode(pair.sign(&payload).0);
+    let tagged = hex::encode(MultiSignature::from(pair.sign(&payload)).encode());
+    assert_eq!(verify_signature(&raw, &ss58_addr, "deadbeef"), Ok(()));
+    assert_eq!(verify_signature(&tagged, &ss58_addr, "deadbeef"), Ok(()));
+  }
+
+  #[tokio::test]
+  async fn it_rejects_a_signature_over_another_payload() {
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
+    let ss58_addr = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
+    let signature = sign_payload(&signing_addr, "deadbeef")
+      .await
+      .unwrap()
+      .signature;
+    let res = verify_signature(&signature, ss58_addr, "cafebabe");
+    assert_eq!(res, Err(VerifyError::InvalidSignature));
+  }
+
+  #[test]
+  fn it_reports_why_a_signature_is_malformed() {
+    let ss58_addr = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
+    let res = verify_signature("zz", ss58_addr, "deadbeef");
+    assert_eq!(res, Err(VerifyError::BadHex("signature")));
+    let res = verify_signature(&"00".repeat(32), ss58_addr, "deadbeef");
+    assert_eq!(res, Err(VerifyError::WrongLength(32)));
+    let res = verify_signature(&"01".repeat(66), ss58_addr, "deadbeef");
+    assert_eq!(res, Err(VerifyError::SchemeMismatch { tag: 1, len: 66 }));
   }
 
   #[tokio::test]
   async fn it_fails_to_sign_if_signing_addr_is_too_short() {
-    let signing_addr = "deadbeef";
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from("deadbeef")));
     let payload = "deadbeef";
-    let signature = sign_payload(signing_addr, payload).await;
+    let signature = sign_payload(&signing_addr, payload).await;
     assert!(signature.is_err());
   }
 
   #[tokio::test]
   async fn it_fails_to_sign_if_payload_has_odd_length() {
-    let signing_addr = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let signing_addr = SignerSpec::from(SignerSource::PrivateKey(String::from(
+      "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e",
+    )));
     let payload = "deadbeef4";
-    let signature = sign_payload(signing_addr, payload).await;
+    let signature = sign_payload(&signing_addr, payload).await;
     assert!(signature.is_err());
   }
 }
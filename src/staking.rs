@@ -1,19 +1,70 @@
-use crate::util;
+use crate::{
+  amount::Balance,
+  block::{AtBlock, BlockId},
+  chain::ChainContext,
+  network::Network,
+  util::{self, SignerSpec, TxOptions, TxReceipt},
+};
 use anyhow::{Context, Result};
 use polymesh_api::{
-  client::{
-    sp_core::crypto::{Ss58AddressFormatRegistry, Ss58Codec},
-    AccountId, MultiAddress,
-  },
+  client::{sp_core::crypto::Ss58Codec, AccountId, MultiAddress, Signer},
   types::pallet_staking::RewardDestination,
-  Api,
+  WrappedCall,
 };
+use serde::Serialize;
+use std::fmt;
 
-/// Declare to nominate `targets` for the origin controller.
-/// Effects will only be felt at the beginning of the next era. This can only be called when
-/// [`EraElectionStatus`] is `Closed`.
-/// The dispatch origin for this call must be signed by the *controller*, not the stash.
-pub async fn nominate(controller_key: &str, operators: Vec<&str>, mainnet: bool) -> Result<String> {
+/// SS58-formatted addresses of the current validator nodes
+#[derive(Serialize, Clone, Debug)]
+pub struct ValidatorList {
+  pub validators: Vec<String>,
+}
+
+impl fmt::Display for ValidatorList {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.validators.join("\n"))
+  }
+}
+
+/// A chunk of stake being unbonded, which can be withdrawn from era `era`
+#[derive(Serialize, Clone, Debug)]
+pub struct UnlockingReport {
+  pub value: Balance,
+  pub era: u32,
+}
+
+/// Stake bonded by a stash and managed by its controller. Amounts are serialized in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct LedgerReport {
+  pub stash: String,
+  pub controller: String,
+  pub symbol: String,
+  /// Bonded stake, including any being unbonded
+  pub total: Balance,
+  /// Bonded stake that counts towards staking
+  pub active: Balance,
+  pub unlocking: Vec<UnlockingReport>,
+  /// Eras whose rewards have been claimed
+  pub claimed_rewards: Vec<u32>,
+}
+
+impl fmt::Display for LedgerReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let symbol = &self.symbol;
+    writeln!(f, "stash: {}", self.stash)?;
+    writeln!(f, "controller: {}", self.controller)?;
+    writeln!(f, "total: {}", self.total.with_symbol(symbol))?;
+    write!(f, "active: {}", self.active.with_symbol(symbol))?;
+    for chunk in &self.unlocking {
+      let value = chunk.value.with_symbol(symbol);
+      write!(f, "\nunlocking: {value} from era {}", chunk.era)?;
+    }
+    Ok(())
+  }
+}
+
+/// The call nominating validators `operators`
+pub fn nominate_call(chain: &ChainContext, operators: &[&str]) -> Result<WrappedCall> {
   let account_ids: Result<Vec<_>, _> = operators
     .iter()
     .map(|&ss58| AccountId::from_string(ss58))
@@ -25,74 +76,89 @@ pub async fn nominate(controller_key: &str, operators: Vec<&str>, mainnet: bool)
     .iter()
     .map(|&id| MultiAddress::from(id))
     .collect();
-  let call = Api::new(util::url(mainnet))
-    .await?
-    .call()
-    .staking()
-    .nominate(targets)?;
-  let mut signer = util::pairsigner_from_private_key(controller_key)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+  Ok(chain.api.call().staking().nominate(targets)?)
 }
 
-/// Take the origin account as a stash and lock up `value` of its balance.
-/// `controller` will be the account that controls it.
-pub async fn bond(
-  stash_key: &str,
+/// Declare to nominate `targets` for the origin controller.
+/// Effects will only be felt at the beginning of the next era. This can only be called when
+/// [`EraElectionStatus`] is `Closed`.
+/// The dispatch origin for this call must be signed by the *controller*, not the stash.
+pub async fn nominate(
+  controller: &SignerSpec,
+  operators: Vec<&str>,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let chain = ChainContext::connect(network).await?;
+  let call = nominate_call(&chain, &operators)?;
+  let mut signer = util::signer(controller)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
+}
+
+/// The call bonding `value` of the origin's balance, controlled by `controller_addr`
+pub fn bond_call(
+  chain: &ChainContext,
   controller_addr: &str,
-  value: u128,
-  mainnet: bool,
-) -> Result<String> {
-  let call = Api::new(util::url(mainnet)).await?.call().staking().bond(
+  value: Balance,
+) -> Result<WrappedCall> {
+  Ok(chain.api.call().staking().bond(
     MultiAddress::from(AccountId::from_string(controller_addr)?),
-    value,
+    value.micro(),
     RewardDestination::Stash,
-  )?;
-  let mut signer = util::pairsigner_from_private_key(stash_key)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+  )?)
 }
 
-/// As a controller, unbond `value` micro-POLYX from being staked by stash.
-#[allow(dead_code)]
-pub async fn unbond(controller_key: &str, value: u128, mainnet: bool) -> Result<String> {
-  let call = Api::new(util::url(mainnet))
-    .await?
-    .call()
-    .staking()
-    .unbond(value)?;
-  let mut signer = util::pairsigner_from_private_key(controller_key)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+/// Take the origin account as a stash and lock up `value` of its balance.
+/// `controller` will be the account that controls it.
+pub async fn bond(
+  stash: &SignerSpec,
+  controller_addr: &str,
+  value: Balance,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let chain = ChainContext::connect(network).await?;
+  let call = bond_call(&chain, controller_addr, value)?;
+  let mut signer = util::signer(stash)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
 }
 
-pub async fn bond_extra(stash_key: &str, amount: u128, mainnet: bool) -> Result<String> {
-  let call = Api::new(util::url(mainnet))
-    .await?
-    .call()
-    .staking()
-    .bond_extra(amount)?;
-  let mut signer = util::pairsigner_from_private_key(stash_key)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+/// As a controller, unbond `value` from being staked by stash.
+#[allow(dead_code)]
+pub async fn unbond(
+  controller: &SignerSpec,
+  value: Balance,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let chain = ChainContext::connect(network).await?;
+  let call = chain.api.call().staking().unbond(value.micro())?;
+  let mut signer = util::signer(controller)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
 }
 
-#[allow(dead_code)]
-pub async fn bond_extra_with_mnemonic(
-  mnemonic: &str,
-  amount: u128,
-  mainnet: bool,
-) -> Result<String> {
-  let api = Api::new(util::url(mainnet)).await?;
-  let call = api.call().staking().bond_extra(amount)?;
-  let mut signer = util::pairsigner_from_mnemonic(mnemonic, None)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+pub async fn bond_extra(
+  stash: &SignerSpec,
+  amount: Balance,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let chain = ChainContext::connect(network).await?;
+  let call = chain.api.call().staking().bond_extra(amount.micro())?;
+  let mut signer = util::signer(stash)?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
 }
 
-/// Withdraw unbonded tokens when [EraElectionStatus] is `Closed`.
-pub async fn withdraw_unbonded(controller_key: &str, mainnet: bool) -> Result<String> {
-  let api = Api::new(util::url(mainnet)).await?;
-  let mut signer = util::pairsigner_from_private_key(controller_key)?;
+/// The call withdrawing the unbonded tokens of `controller`
+pub async fn withdraw_unbonded_call(
+  chain: &ChainContext,
+  controller: AccountId,
+) -> Result<WrappedCall> {
+  let api = &chain.api;
   let ledger = api
     .query()
     .staking()
-    .ledger(signer.account)
+    .ledger(controller)
     .await?
     .context("no ledger found")?;
   // Get slashing spans of stash account
@@ -104,18 +170,69 @@ pub async fn withdraw_unbonded(controller_key: &str, mainnet: bool) -> Result<St
       Some(spans) => (spans.prior.len() + 1) as u32, // number of prior spans + last span
     }
   };
+  Ok(api.call().staking().withdraw_unbonded(num_slashing_spans)?)
+}
 
-  let call = api.call().staking().withdraw_unbonded(num_slashing_spans)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+/// Withdraw unbonded tokens when [EraElectionStatus] is `Closed`.
+pub async fn withdraw_unbonded(
+  controller: &SignerSpec,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TxReceipt> {
+  let chain = ChainContext::connect(network).await?;
+  let mut signer = util::signer(controller)?;
+  let call = withdraw_unbonded_call(&chain, signer.account()).await?;
+  util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await
+}
+
+/// Get the staking ledger of a stash or controller address, at block `at` if set
+pub async fn ledger(
+  addr: &str,
+  network: &Network,
+  at: Option<BlockId>,
+) -> Result<AtBlock<LedgerReport>> {
+  let account = AccountId::from_string(addr)?;
+  let (chain, block) = ChainContext::connect_at(network, at).await?;
+  // Ledgers are keyed by controller, so a stash is first mapped to its controller
+  let controller = chain
+    .query()
+    .staking()
+    .bonded(account)
+    .await?
+    .unwrap_or(account);
+  let ledger = chain
+    .query()
+    .staking()
+    .ledger(controller)
+    .await?
+    .with_context(|| format!("{addr} is neither a stash nor a controller"))?;
+  let report = LedgerReport {
+    stash: chain.ss58check(&ledger.stash),
+    controller: chain.ss58check(&controller),
+    symbol: chain.symbol.clone(),
+    total: Balance::from_micro(ledger.total),
+    active: Balance::from_micro(ledger.active),
+    unlocking: ledger
+      .unlocking
+      .iter()
+      .map(|chunk| UnlockingReport {
+        value: Balance::from_micro(chunk.value),
+        era: chunk.era,
+      })
+      .collect(),
+    claimed_rewards: ledger.claimed_rewards,
+  };
+  Ok(AtBlock { report, block })
 }
 
 #[allow(dead_code)]
-pub async fn active_in_ledger(controller_key: &str, mainnet: bool) -> Result<u128> {
-  let ledger = Api::new(util::url(mainnet))
+pub async fn active_in_ledger(controller: &SignerSpec, network: &Network) -> Result<u128> {
+  let ledger = ChainContext::connect(network)
     .await?
+    .api
     .query()
     .staking()
-    .ledger(util::pairsigner_from_private_key(controller_key)?.account)
+    .ledger(util::signer(controller)?.account())
     .await?
     .context("no ledger found")?;
   Ok(ledger.active)
@@ -123,61 +240,60 @@ pub async fn active_in_ledger(controller_key: &str, mainnet: bool) -> Result<u12
 
 /// Get sum of all staking rewards
 #[allow(dead_code)]
-pub async fn total_rewarded(controller_key: &str, mainnet: bool) -> Result<u32> {
-  let ledger = Api::new(util::url(mainnet))
+pub async fn total_rewarded(controller: &SignerSpec, network: &Network) -> Result<u32> {
+  let ledger = ChainContext::connect(network)
     .await?
+    .api
     .query()
     .staking()
-    .ledger(util::pairsigner_from_private_key(controller_key)?.account)
+    .ledger(util::signer(controller)?.account())
     .await?
     .context("not a controller")?;
   Ok(ledger.claimed_rewards.iter().sum())
 }
 
 /// The AccountIds (public) of validator nodes
-pub async fn validators(mainnet: bool) -> Result<Vec<String>> {
-  let account_ids = Api::new(util::url(mainnet))
-    .await?
-    .query()
-    .session()
-    .validators()
-    .await?;
-  let operators = if mainnet {
-    account_ids
-      .iter()
-      .map(|id| id.to_ss58check_with_version(Ss58AddressFormatRegistry::PolymeshAccount.into()))
-      .collect()
-  } else {
-    account_ids.iter().map(|id| id.to_ss58check()).collect()
-  };
-  Ok(operators)
+pub async fn validators(network: &Network) -> Result<ValidatorList> {
+  let chain = ChainContext::connect(network).await?;
+  let account_ids = chain.api.query().session().validators().await?;
+  let validators = account_ids.iter().map(|id| chain.ss58check(id)).collect();
+  Ok(ValidatorList { validators })
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::util::SignerSource;
 
   #[tokio::test]
   async fn it_gets_validators_on_testnet() {
-    let mainnet = false;
-    let res = validators(mainnet).await;
+    let network = Network::testnet();
+    let res = validators(&network).await;
     assert!(res.is_ok());
   }
 
   #[tokio::test]
   async fn it_gets_validators_on_mainnet() {
-    let mainnet = true;
-    let res = validators(mainnet).await;
+    let network = Network::mainnet();
+    let res = validators(&network).await;
     assert!(res.is_ok());
   }
 
+  #[tokio::test]
+  async fn it_rejects_ledgers_of_unbonded_accounts() {
+    let addr = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
+    let res = ledger(addr, &Network::testnet(), None).await;
+    assert!(res.is_err());
+  }
+
   #[tokio::test]
   #[ignore]
   async fn it_unbonds() {
-    let mainnet = false;
-    let value = 80 * 1_000_000;
+    let network = Network::testnet();
+    let value: Balance = "80".parse().unwrap();
     let controller_key = "9a62194397c8ccd1a8b4546afa594937e75f469381739829f979ce459910a584";
-    let res = unbond(controller_key, value, mainnet).await;
+    let controller = SignerSpec::from(SignerSource::PrivateKey(String::from(controller_key)));
+    let res = unbond(&controller, value, &network, &TxOptions::default()).await;
     assert!(res.is_ok());
     println!("{}", res.unwrap());
     assert!(false);
@@ -186,12 +302,13 @@ mod tests {
   #[tokio::test]
   #[ignore]
   async fn it_withdraws_unbonded() {
-    let mainnet = false;
+    let network = Network::testnet();
     // let controller_key = "9173628750a527f9cdaa69ecbec47b11981299c4e47307b2d7df75a8b0f7d01f";
     // let controller_key = "9a62194397c8ccd1a8b4546afa594937e75f469381739829f979ce459910a584";
     // gnarwhal
     let controller_key = "88a3c978f0ebcda75605516e8c7bdc1a437fff484c1a4c24a663f7149e1271e2";
-    let res = withdraw_unbonded(controller_key, mainnet).await;
+    let controller = SignerSpec::from(SignerSource::PrivateKey(String::from(controller_key)));
+    let res = withdraw_unbonded(&controller, &network, &TxOptions::default()).await;
     assert!(res.is_ok());
     println!("Result: {}", res.unwrap());
     assert!(false);
@@ -199,11 +316,12 @@ mod tests {
 
   #[tokio::test]
   async fn it_gets_sum_of_rewards() {
-    let mainnet = false;
+    let network = Network::testnet();
     // not a controller (all unbonded)
     // let controller_key = "88a3c978f0ebcda75605516e8c7bdc1a437fff484c1a4c24a663f7149e1271e2"; //
     let controller_key = "9173628750a527f9cdaa69ecbec47b11981299c4e47307b2d7df75a8b0f7d01f";
-    let res = total_rewarded(controller_key, mainnet).await;
+    let controller = SignerSpec::from(SignerSource::PrivateKey(String::from(controller_key)));
+    let res = total_rewarded(&controller, &network).await;
     assert!(res.is_ok());
     let rewards = res.unwrap();
     println!("Rewards: {} POLYX", rewards as f64 * 1e-6);
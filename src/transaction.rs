@@ -1,43 +1,507 @@
-use crate::util;
-use anyhow::Result;
+use crate::{
+  amount::Balance,
+  chain::ChainContext,
+  metadata::{DispatchErrorReport, EventReport},
+  network::Network,
+  output::{ErrorCode, Failure},
+  util::{self, AnySigner, Nonce, SignerSpec, TxOptions, TxReceipt},
+};
+use anyhow::{Context, Result};
 use polymesh_api::{
-  client::{sp_core::crypto::Ss58Codec, AccountId},
-  Api,
+  client::{sp_core::crypto::Ss58Codec, AccountId, Signer},
+  types::polymesh_primitives::Memo,
+  WrappedCall,
+};
+use serde::Serialize;
+use std::{
+  fmt, fs,
+  io::{self, Read},
 };
 
-/// Create a transaction. Transaction is then signed with a private key and submitted on-chain. The
-/// input dest should be an Ss58-encoded &str, e.g. "5EEiPC3dQ6dvYHQmovFzvpLbsMzCCoCax2oekPBVyq84bWG4"
+/// Longest memo a transfer may carry, in bytes
+const MEMO_LEN: usize = 32;
+
+/// A submitted POLYX transfer
+#[derive(Serialize, Clone, Debug)]
+pub struct TransferReceipt {
+  #[serde(flatten)]
+  pub tx: TxReceipt,
+  pub from: String,
+  pub to: String,
+  pub symbol: String,
+  /// Amount transferred in μPOLYX
+  pub amount: Balance,
+}
+
+impl fmt::Display for TransferReceipt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "from: {}", self.from)?;
+    writeln!(f, "to: {}", self.to)?;
+    writeln!(f, "amount: {}", self.amount.with_symbol(&self.symbol))?;
+    write!(f, "{}", self.tx)
+  }
+}
+
+/// The call transferring `amount` to `dest`
+pub fn transfer_call(
+  chain: &ChainContext,
+  dest: AccountId,
+  amount: Balance,
+) -> Result<WrappedCall> {
+  Ok(
+    chain
+      .api
+      .call()
+      .balances()
+      .transfer(dest.into(), amount.micro())?,
+  )
+}
+
+/// Create a transaction. Transaction is then signed by the signing account and submitted on-chain.
+/// The input dest should be an Ss58-encoded &str, e.g. "5EEiPC3dQ6dvYHQmovFzvpLbsMzCCoCax2oekPBVyq84bWG4"
 pub async fn withdraw(
-  signing_key: &str,
-  dest: &str, // An SS58-encoded adress
-  amount: u128,
-  mainnet: bool,
-) -> Result<String> {
-  let dest = AccountId::from_ss58check(dest)?;
-  let call = Api::new(util::url(mainnet))
-    .await?
-    .call()
-    .balances()
-    .transfer(dest.into(), amount)?;
-  let mut signer = util::pairsigner_from_private_key(signing_key)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
-}
-
-/// Create a transaction. Transaction is then signed with a private key and submitted on-chain.
-pub async fn withdraw_with_mnemonic(
-  mnemonic: &str,
+  signer: &SignerSpec,
   dest: &str, // An SS58-encoded adress
-  amount: u128,
-  mainnet: bool,
-) -> Result<String> {
-  // The input dest should be an SS58-encoded string, e.g.
-  // "5EEiPC3dQ6dvYHQmovFzvpLbsMzCCoCax2oekPBVyq84bWG4"
+  amount: Balance,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<TransferReceipt> {
   let dest = AccountId::from_ss58check(dest)?;
-  let call = Api::new(util::url(mainnet))
-    .await?
-    .call()
-    .balances()
-    .transfer(dest.into(), amount)?;
-  let mut signer = util::pairsigner_from_mnemonic(mnemonic, None)?;
-  util::sign_submit_and_watch(&call, &mut signer).await
+  let chain = ChainContext::connect(network).await?;
+  let call = transfer_call(&chain, dest, amount)?;
+  let mut signer = util::signer(signer)?;
+  let tx = util::sign_submit_and_watch(&chain, &call, &mut signer, tx).await?;
+  Ok(TransferReceipt {
+    tx,
+    from: chain.ss58check(&signer.account()),
+    to: chain.ss58check(&dest),
+    symbol: chain.symbol.clone(),
+    amount,
+  })
+}
+
+/// A transfer read from a row of a batch file
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct BatchItem {
+  /// Line of the row in the batch file
+  pub line: usize,
+  pub destination: String,
+  /// Amount to transfer in μPOLYX
+  pub amount: Balance,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub memo: Option<String>,
+}
+
+/// What became of a transfer of a batch
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ItemStatus {
+  Completed,
+  /// Not submitted, since its batch or an earlier one couldn't be submitted
+  NotExecuted,
+  /// Undone along with the rest of its batch, since one of the batch's transfers failed with
+  /// `error`. The chain doesn't report which transfer that was.
+  Reverted {
+    error: DispatchErrorReport,
+  },
+  /// Unknown, since its batch wasn't waited for or was only dry-run
+  Unknown,
+}
+
+impl fmt::Display for ItemStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Completed => write!(f, "completed"),
+      Self::NotExecuted => write!(f, "not executed"),
+      Self::Reverted { error } => write!(f, "reverted: {error}"),
+      Self::Unknown => write!(f, "unknown"),
+    }
+  }
+}
+
+/// A transfer of a batch along with what became of it
+#[derive(Serialize, Clone, Debug)]
+pub struct ItemReport {
+  #[serde(flatten)]
+  pub item: BatchItem,
+  #[serde(flatten)]
+  pub status: ItemStatus,
+}
+
+/// A `utility.batch_all` of transfers, and its receipt if it was submitted
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchReceipt {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tx: Option<TxReceipt>,
+  /// Why the batch couldn't be submitted, if it is the one that stopped the rest
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  pub items: Vec<ItemReport>,
+}
+
+/// Transfers from one account submitted in batches. Amounts are serialized in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchTransferReport {
+  pub from: String,
+  pub symbol: String,
+  /// Sum of the amounts of all transfers
+  pub total: Balance,
+  pub batches: Vec<BatchReceipt>,
+}
+
+impl BatchTransferReport {
+  /// Whether any transfer is known not to have been made
+  fn failed(&self) -> bool {
+    self
+      .batches
+      .iter()
+      .flat_map(|batch| &batch.items)
+      .any(|item| !matches!(item.status, ItemStatus::Completed | ItemStatus::Unknown))
+  }
+}
+
+impl fmt::Display for BatchTransferReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let transfers: usize = self.batches.iter().map(|batch| batch.items.len()).sum();
+    writeln!(f, "from: {}", self.from)?;
+    write!(
+      f,
+      "total: {} in {transfers} transfers",
+      self.total.with_symbol(&self.symbol)
+    )?;
+    for (i, batch) in self.batches.iter().enumerate() {
+      write!(f, "\nbatch {} of {}:", i + 1, self.batches.len())?;
+      match (&batch.tx, &batch.error) {
+        (Some(tx), _) => write!(f, "\n{tx}")?,
+        (None, Some(error)) => write!(f, " not submitted: {error}")?,
+        (None, None) => write!(f, " not submitted")?,
+      }
+      write!(f, "\ntransfers:")?;
+      for ItemReport { item, status } in &batch.items {
+        let amount = item.amount.with_symbol(&self.symbol);
+        write!(
+          f,
+          "\n  line {}: {amount} to {}",
+          item.line, item.destination
+        )?;
+        if let Some(memo) = &item.memo {
+          write!(f, " ({memo})")?;
+        }
+        write!(f, ": {status}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Read transfers from a CSV file of `destination,amount[,memo]` rows, or from stdin if `path` is
+/// `-`. Amounts are whole numbers of μPOLYX if `raw` is set.
+pub fn read_batch(path: &str, raw: bool) -> Result<Vec<BatchItem>> {
+  let contents = if path == "-" {
+    let mut contents = String::new();
+    io::stdin().read_to_string(&mut contents)?;
+    contents
+  } else {
+    fs::read_to_string(path).with_context(|| format!("could not read transfers from {path}"))?
+  };
+  parse_batch(&contents, raw)
+}
+
+/// Parse and validate every row of a batch file, skipping blank lines, `#` comments and a
+/// `destination,amount,memo` header, and failing with the errors of all invalid rows
+fn parse_batch(contents: &str, raw: bool) -> Result<Vec<BatchItem>> {
+  let mut items = Vec::new();
+  let mut errors = Vec::new();
+  for (i, row) in contents.lines().enumerate() {
+    let line = i + 1;
+    let row = row.trim();
+    if row.is_empty() || row.starts_with('#') {
+      continue;
+    }
+    // Memos may contain commas
+    let fields: Vec<&str> = row.splitn(3, ',').map(str::trim).collect();
+    if items.is_empty() && errors.is_empty() && fields[0].eq_ignore_ascii_case("destination") {
+      continue;
+    }
+    match parse_row(&fields, raw) {
+      Ok((destination, amount, memo)) => items.push(BatchItem {
+        line,
+        destination,
+        amount,
+        memo,
+      }),
+      Err(error) => errors.push(format!("line {line}: {error}")),
+    }
+  }
+  if !errors.is_empty() {
+    return Err(Failure::new(ErrorCode::InvalidInput, errors.join("\n")).into());
+  }
+  if items.is_empty() {
+    return Err(Failure::new(ErrorCode::InvalidInput, "there are no transfers to make").into());
+  }
+  Ok(items)
+}
+
+/// Validate the destination, amount and memo of a row of a batch file
+fn parse_row(fields: &[&str], raw: bool) -> Result<(String, Balance, Option<String>), String> {
+  let (destination, value, memo) = match *fields {
+    [destination, value] | [destination, value, ""] => (destination, value, None),
+    [destination, value, memo] => (destination, value, Some(memo)),
+    _ => return Err(String::from("expected destination,amount[,memo]")),
+  };
+  if AccountId::from_ss58check(destination).is_err() {
+    return Err(format!("invalid destination {destination}"));
+  }
+  let amount = if raw {
+    Balance::parse_raw(value)
+  } else {
+    value.parse()
+  };
+  let amount = amount.map_err(|e| format!("invalid amount {value}: {e}"))?;
+  if let Some(memo) = memo.filter(|memo| memo.len() > MEMO_LEN) {
+    return Err(format!("memo {memo} is longer than {MEMO_LEN} bytes"));
+  }
+  Ok((String::from(destination), amount, memo.map(String::from)))
+}
+
+/// The call making the transfer of `item`, with its memo if it has one
+fn item_call(chain: &ChainContext, item: &BatchItem) -> Result<WrappedCall> {
+  let dest = AccountId::from_ss58check(&item.destination)?;
+  Ok(match &item.memo {
+    Some(memo) => {
+      // Memos are zero-padded to 32 bytes
+      let mut bytes = [0; MEMO_LEN];
+      bytes[..memo.len()].copy_from_slice(memo.as_bytes());
+      chain.api.call().balances().transfer_with_memo(
+        dest.into(),
+        item.amount.micro(),
+        Some(Memo(bytes)),
+      )?
+    }
+    None => transfer_call(chain, dest, item.amount)?,
+  })
+}
+
+/// What became of each of `count` transfers of a batch, read from the outcome of the transaction
+/// that submitted it. `batch_all` is atomic, so if a transfer fails, the whole batch is undone
+/// along with its events, and the transaction fails with that transfer's `error`.
+fn item_statuses(
+  events: &[EventReport],
+  success: bool,
+  error: Option<&DispatchErrorReport>,
+  count: usize,
+) -> Vec<ItemStatus> {
+  let status = if !success {
+    ItemStatus::Reverted {
+      error: error.cloned().unwrap_or_else(|| DispatchErrorReport {
+        name: String::from("unknown error"),
+        docs: String::new(),
+      }),
+    }
+  } else if events
+    .iter()
+    .any(|event| event.pallet == "Utility" && event.name == "BatchCompleted")
+  {
+    ItemStatus::Completed
+  } else {
+    ItemStatus::Unknown
+  };
+  vec![status; count]
+}
+
+/// Sign and submit a `utility.batch_all` of the transfers of `items`, then set the nonce of the
+/// next batch in `options` unless it is a dry run, since those are all checked against the
+/// account's current state
+async fn submit_batch(
+  chain: &ChainContext,
+  signer: &AnySigner,
+  items: &[BatchItem],
+  options: &mut TxOptions,
+) -> Result<TxReceipt> {
+  let calls = items
+    .iter()
+    .map(|item| Ok(item_call(chain, item)?.runtime_call().clone()))
+    .collect::<Result<Vec<_>>>()?;
+  let call = chain.api.call().utility().batch_all(calls)?;
+  let unsigned = util::unsigned_tx(chain, &call, signer.account(), options).await?;
+  let extrinsic = unsigned.sign(signer).await?;
+  let receipt = util::submit(chain, &extrinsic, options).await?;
+  if !options.dry_run {
+    options.nonce = Nonce::Exact(unsigned.nonce + 1);
+  }
+  Ok(receipt)
+}
+
+/// Make many transfers from one account, packed into `utility.batch_all` calls of at most
+/// `max_per_batch` transfers each. Batches after the first are signed with the nonces following
+/// the first's, so that they can be submitted without waiting for each other. If a batch can't be
+/// submitted, the rest aren't either. Fails with the report if any transfer is known not to have
+/// been made.
+pub async fn batch(
+  signer: &SignerSpec,
+  items: &[BatchItem],
+  max_per_batch: usize,
+  network: &Network,
+  tx: &TxOptions,
+) -> Result<BatchTransferReport> {
+  let signer = util::signer(signer)?;
+  let chain = ChainContext::connect(network).await?;
+  chain.ensure_expected_chain()?;
+  let chunks: Vec<&[BatchItem]> = items.chunks(max_per_batch.max(1)).collect();
+  let mut options = tx.clone();
+  let mut batches = Vec::new();
+  let mut unsent = false;
+  for chunk in &chunks {
+    let (receipt, error) = if unsent {
+      (None, None)
+    } else {
+      match submit_batch(&chain, &signer, chunk, &mut options).await {
+        Ok(receipt) => (Some(receipt), None),
+        Err(error) => {
+          unsent = true;
+          (None, Some(format!("{error:#}")))
+        }
+      }
+    };
+    let statuses = match receipt.as_ref().map(|receipt| &receipt.outcome) {
+      Some(Some(outcome)) => item_statuses(
+        &outcome.events,
+        outcome.success,
+        outcome.error.as_ref(),
+        chunk.len(),
+      ),
+      Some(None) => vec![ItemStatus::Unknown; chunk.len()],
+      None => vec![ItemStatus::NotExecuted; chunk.len()],
+    };
+    let items = chunk
+      .iter()
+      .cloned()
+      .zip(statuses)
+      .map(|(item, status)| ItemReport { item, status })
+      .collect();
+    batches.push(BatchReceipt {
+      tx: receipt,
+      error,
+      items,
+    });
+  }
+  let report = BatchTransferReport {
+    from: chain.ss58check(&signer.account()),
+    symbol: chain.symbol.clone(),
+    total: Balance::from_micro(items.iter().map(|item| item.amount.micro()).sum()),
+    batches,
+  };
+  if report.failed() {
+    let code = if unsent {
+      ErrorCode::Rpc
+    } else {
+      ErrorCode::DispatchFailed
+    };
+    let failure = Failure::new(code, report.to_string());
+    return Err(failure.with_details(&report).into());
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use scale_value::Composite;
+
+  const DEST: &str = "5FPAYmXzQhLvFQggnYGNAgrkrUB3GCSoWAfT3NS2ageeGqtt";
+
+  #[test]
+  fn it_parses_batch_files() {
+    let contents = format!(
+      "destination,amount,memo\n# payroll\n{DEST},1.5\n\n{DEST}, 2mPOLYX ,invoice 7, March\n"
+    );
+    let items = parse_batch(&contents, false).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].line, 3);
+    assert_eq!(items[0].amount, Balance::from_micro(1_500_000));
+    assert_eq!(items[0].memo, None);
+    assert_eq!(items[1].amount, Balance::from_micro(2_000));
+    assert_eq!(items[1].memo.as_deref(), Some("invoice 7, March"));
+
+    let raw = parse_batch(&format!("{DEST},1500000"), true).unwrap();
+    assert_eq!(raw[0].amount, Balance::from_micro(1_500_000));
+    assert!(parse_batch("# nothing\n", false).is_err());
+  }
+
+  #[test]
+  fn it_reports_every_invalid_row() {
+    let memo = "m".repeat(MEMO_LEN + 1);
+    let contents = format!("5Abc,1\n{DEST},-1\n{DEST}\n{DEST},1,{memo}\n{DEST},1\n");
+    let error = parse_batch(&contents, false).unwrap_err().to_string();
+    let lines: Vec<&str> = error.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("line 1: invalid destination"));
+    assert!(lines[1].starts_with("line 2: invalid amount"));
+    assert!(lines[2].starts_with("line 3: expected"));
+    assert!(lines[3].starts_with("line 4: memo"));
+  }
+
+  #[test]
+  fn it_reads_item_statuses_from_batch_outcomes() {
+    let completed = [EventReport {
+      pallet: String::from("Utility"),
+      name: String::from("BatchCompleted"),
+      fields: Composite::Unnamed(Vec::new()),
+    }];
+    let statuses = item_statuses(&completed, true, None, 2);
+    assert_eq!(statuses, [ItemStatus::Completed, ItemStatus::Completed]);
+    assert_eq!(item_statuses(&[], true, None, 1), [ItemStatus::Unknown]);
+
+    // A failed `batch_all` leaves no batch events, only the error of the transfer that failed
+    let error = DispatchErrorReport {
+      name: String::from("Balances.InsufficientBalance"),
+      docs: String::from("Balance too low to send value"),
+    };
+    let statuses = item_statuses(&[], false, Some(&error), 2);
+    assert_eq!(statuses, vec![ItemStatus::Reverted { error }; 2]);
+    assert_eq!(
+      statuses[0].to_string(),
+      "reverted: Balances.InsufficientBalance: Balance too low to send value"
+    );
+  }
+
+  #[test]
+  fn it_reports_batches_that_were_not_submitted() {
+    let item = |line, status| ItemReport {
+      item: BatchItem {
+        line,
+        destination: String::from(DEST),
+        amount: Balance::from_micro(1),
+        memo: None,
+      },
+      status,
+    };
+    let sent = TxReceipt {
+      hash: String::from("0x01"),
+      outcome: None,
+      dry_run: None,
+    };
+    let report = BatchTransferReport {
+      from: String::from(DEST),
+      symbol: String::from("POLYX"),
+      total: Balance::from_micro(2),
+      batches: vec![
+        BatchReceipt {
+          tx: Some(sent),
+          error: None,
+          items: vec![item(1, ItemStatus::Unknown)],
+        },
+        BatchReceipt {
+          tx: None,
+          error: Some(String::from("connection closed")),
+          items: vec![item(2, ItemStatus::NotExecuted)],
+        },
+      ],
+    };
+    assert!(report.failed());
+    let text = report.to_string();
+    assert!(text.contains("batch 1 of 2:\n0x01\n"));
+    assert!(text.contains("batch 2 of 2: not submitted: connection closed"));
+    assert!(text.ends_with(": not executed"));
+  }
 }
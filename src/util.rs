@@ -1,55 +1,838 @@
-use anyhow::Result;
+use crate::{
+  amount::Balance,
+  chain::ChainContext,
+  extrinsic::UnsignedTx,
+  keys,
+  metadata::{DispatchErrorReport, EventReport, Metadata},
+  output::{ErrorCode, Failure},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use jsonrpsee_core::{client::Subscription, rpc_params};
 use polymesh_api::{
   client::{
-    sp_core::{sr25519, Pair},
-    PairSigner, Signer,
+    sp_core::{
+      crypto::{ByteArray, SecretUri},
+      ecdsa, ed25519,
+      hashing::blake2_256,
+      sr25519, Pair,
+    },
+    AccountId, BlockHash, MultiSignature, PairSigner, Signer,
   },
   WrappedCall,
 };
+use scale_value::At;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Where the secret of a signing account comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignerSource {
+  /// Hexadecimal private key: a 32-byte seed, or a 64-byte sr25519 secret key as imported from
+  /// key files
+  PrivateKey(String),
+  /// BIP39 mnemonic phrase
+  Mnemonic(String),
+  /// Alias of an account held in the encrypted keystore
+  Account(String),
+}
+
+/// Signature scheme of a signing account
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+  #[default]
+  Sr25519,
+  Ed25519,
+  Ecdsa,
+}
+
+impl FromStr for Scheme {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "sr25519" => Ok(Self::Sr25519),
+      "ed25519" => Ok(Self::Ed25519),
+      "ecdsa" => Ok(Self::Ecdsa),
+      _ => bail!("unknown signature scheme {s}, expected sr25519, ed25519 or ecdsa"),
+    }
+  }
+}
+
+impl fmt::Display for Scheme {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Sr25519 => write!(f, "sr25519"),
+      Self::Ed25519 => write!(f, "ed25519"),
+      Self::Ecdsa => write!(f, "ecdsa"),
+    }
+  }
+}
+
+/// A signing account: where its secret comes from, plus its signature scheme, the mnemonic
+/// password and the derivation path (e.g. `//polymesh//0`) applied to that secret
+#[derive(Clone)]
+pub struct SignerSpec {
+  pub source: SignerSource,
+  /// If unset, the scheme of a keystore account, or else sr25519
+  pub scheme: Option<Scheme>,
+  pub password: Option<String>,
+  pub derivation: Option<String>,
+}
+
+impl From<SignerSource> for SignerSpec {
+  fn from(source: SignerSource) -> Self {
+    Self {
+      source,
+      scheme: None,
+      password: None,
+      derivation: None,
+    }
+  }
+}
+
+/// A signer for any of the signature schemes supported by Polymesh. The account of an ecdsa
+/// signer is the blake2 hash of its compressed public key.
+pub enum AnySigner {
+  Sr25519(PairSigner<sr25519::Pair>),
+  Ed25519(PairSigner<ed25519::Pair>),
+  Ecdsa(PairSigner<ecdsa::Pair>),
+}
+
+impl AnySigner {
+  pub fn scheme(&self) -> Scheme {
+    match self {
+      Self::Sr25519(_) => Scheme::Sr25519,
+      Self::Ed25519(_) => Scheme::Ed25519,
+      Self::Ecdsa(_) => Scheme::Ecdsa,
+    }
+  }
+
+  /// Raw public key (33 bytes for ecdsa, otherwise 32 bytes)
+  pub fn public_key(&self) -> Vec<u8> {
+    match self {
+      Self::Sr25519(signer) => signer.pair.public().to_raw_vec(),
+      Self::Ed25519(signer) => signer.pair.public().to_raw_vec(),
+      Self::Ecdsa(signer) => signer.pair.public().to_raw_vec(),
+    }
+  }
+}
+
+#[async_trait]
+impl Signer for AnySigner {
+  fn account(&self) -> AccountId {
+    match self {
+      Self::Sr25519(signer) => signer.account(),
+      Self::Ed25519(signer) => signer.account(),
+      Self::Ecdsa(signer) => signer.account(),
+    }
+  }
+
+  async fn nonce(&self) -> Option<u32> {
+    match self {
+      Self::Sr25519(signer) => signer.nonce().await,
+      Self::Ed25519(signer) => signer.nonce().await,
+      Self::Ecdsa(signer) => signer.nonce().await,
+    }
+  }
+
+  async fn set_nonce(&mut self, nonce: u32) {
+    match self {
+      Self::Sr25519(signer) => signer.set_nonce(nonce).await,
+      Self::Ed25519(signer) => signer.set_nonce(nonce).await,
+      Self::Ecdsa(signer) => signer.set_nonce(nonce).await,
+    }
+  }
+
+  async fn sign(&self, msg: &[u8]) -> polymesh_api::client::error::Result<MultiSignature> {
+    match self {
+      Self::Sr25519(signer) => signer.sign(msg).await,
+      Self::Ed25519(signer) => signer.sign(msg).await,
+      Self::Ecdsa(signer) => signer.sign(msg).await,
+    }
+  }
+}
+
+/// Build a key pair of any scheme from a hexadecimal private key or a mnemonic, along an
+/// optional derivation path
+fn pair<P: Pair>(source: &SignerSource, password: Option<&str>, derivation: &str) -> Result<P> {
+  let pair = match source {
+    SignerSource::PrivateKey(key) => {
+      let pair = P::from_seed_slice(&hex::decode(key.strip_prefix("0x").unwrap_or(key))?)?;
+      if derivation.is_empty() {
+        return Ok(pair);
+      }
+      // Derived from the pair rather than a `0x<seed><path>` secret URI, which only takes 32-byte
+      // seeds and not the 64-byte sr25519 secrets imported from key files
+      if !derivation.starts_with('/') {
+        bail!("derivation path must start with / or //, got {derivation}");
+      }
+      let path: SecretUri = derivation
+        .parse()
+        .map_err(|e| anyhow!("invalid derivation path {derivation}: {e:?}"))?;
+      pair
+        .derive(path.junctions.into_iter(), None)
+        .map_err(|e| anyhow!("could not derive {derivation}: {e:?}"))?
+        .0
+    }
+    SignerSource::Mnemonic(mnemonic) => {
+      P::from_string(&format!("{mnemonic}{derivation}"), password)?
+    }
+    SignerSource::Account(alias) => bail!("keystore account {alias} must be decrypted first"),
+  };
+  Ok(pair)
+}
+
+/// Resolve a signing account from wherever its secret is held, prompting for the keystore
+/// passphrase if the account is stored in the keystore.
+pub fn signer(spec: &SignerSpec) -> Result<AnySigner> {
+  let (source, scheme) = match &spec.source {
+    SignerSource::Account(alias) => {
+      let keystore = keys::Keystore::open()?;
+      let stored = keystore.get(alias)?.scheme;
+      if let Some(scheme) = spec.scheme.filter(|scheme| *scheme != stored) {
+        bail!("{alias} holds a {stored} key, not {scheme}");
+      }
+      (keystore.decrypt(alias, &keys::passphrase(false)?)?, stored)
+    }
+    source => (source.clone(), spec.scheme.unwrap_or_default()),
+  };
+  let password = spec.password.as_deref();
+  let derivation = spec.derivation.as_deref().unwrap_or_default();
+  Ok(match scheme {
+    Scheme::Sr25519 => AnySigner::Sr25519(PairSigner::new(pair(&source, password, derivation)?)),
+    Scheme::Ed25519 => AnySigner::Ed25519(PairSigner::new(pair(&source, password, derivation)?)),
+    Scheme::Ecdsa => AnySigner::Ecdsa(PairSigner::new(pair(&source, password, derivation)?)),
+  })
+}
+
+/// How long to wait for a submitted transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wait {
+  /// Return as soon as the node accepts the transaction
+  None,
+  /// Wait until the transaction is included in a block
+  #[default]
+  InBlock,
+  /// Wait until the block including the transaction is finalized
+  Finalized,
+}
+
+impl FromStr for Wait {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "none" => Ok(Self::None),
+      "inblock" => Ok(Self::InBlock),
+      "finalized" => Ok(Self::Finalized),
+      _ => bail!("unknown wait {s}, expected none, inblock or finalized"),
+    }
+  }
+}
+
+/// Which nonce to sign transactions with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nonce {
+  /// The account's nonce on chain, which ignores its transactions still in the pool
+  #[default]
+  Auto,
+  /// The account's next nonce as reported by the node, counting its transactions in the pool
+  AutoPending,
+  Exact(u32),
+}
+
+impl FromStr for Nonce {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "auto" => Ok(Self::Auto),
+      "auto-pending" => Ok(Self::AutoPending),
+      _ => match s.parse() {
+        Ok(nonce) => Ok(Self::Exact(nonce)),
+        Err(_) => bail!("invalid nonce {s}, expected a number, auto or auto-pending"),
+      },
+    }
+  }
+}
+
+/// How long transactions stay valid for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mortality {
+  #[default]
+  Immortal,
+  /// Valid for about this many blocks from the latest finalized block
+  Blocks(u64),
+}
+
+impl FromStr for Mortality {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "immortal" => Ok(Self::Immortal),
+      _ => match s.parse() {
+        Ok(blocks) if blocks > 0 => Ok(Self::Blocks(blocks)),
+        _ => bail!("invalid mortality {s}, expected a positive number of blocks or immortal"),
+      },
+    }
+  }
+}
+
+/// How transactions are signed and submitted
+#[derive(Clone, Debug, Default)]
+pub struct TxOptions {
+  pub wait: Wait,
+  /// Sign and dry-run transactions without submitting them
+  pub dry_run: bool,
+  pub nonce: Nonce,
+  pub mortality: Mortality,
+  /// Tip for the block author
+  pub tip: Balance,
+}
+
+/// What a transaction did in the block that included it. The fee is serialized in μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct TxOutcome {
+  pub block_hash: String,
+  pub finalized: bool,
+  /// Index of the extrinsic in its block
+  pub extrinsic_index: u32,
+  pub success: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<DispatchErrorReport>,
+  /// Fee paid, if the runtime reports it
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fee: Option<Balance>,
+  pub events: Vec<EventReport>,
+  #[serde(skip)]
+  symbol: String,
+}
+
+impl fmt::Display for TxOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let status = if self.finalized {
+      "finalized"
+    } else {
+      "in block"
+    };
+    writeln!(f, "block: {} ({status})", self.block_hash)?;
+    writeln!(f, "extrinsic: {}", self.extrinsic_index)?;
+    if let Some(fee) = self.fee {
+      writeln!(f, "fee: {}", fee.with_symbol(&self.symbol))?;
+    }
+    match &self.error {
+      Some(error) => writeln!(f, "result: failed: {error}")?,
+      None => writeln!(f, "result: success")?,
+    }
+    write!(f, "events:")?;
+    for event in &self.events {
+      write!(f, "\n  {event}")?;
+    }
+    Ok(())
+  }
+}
+
+/// What `system_dryRun` says a transaction would do
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum DryRunResult {
+  Success,
+  /// The transaction is valid but its call would fail
+  Failed {
+    error: DispatchErrorReport,
+  },
+  /// The transaction would be rejected, e.g. for a bad nonce or too low a balance for its fee
+  Invalid {
+    reason: String,
+  },
+  /// The node doesn't allow dry runs, as is common for public nodes
+  Unavailable {
+    reason: String,
+  },
+}
+
+impl fmt::Display for DryRunResult {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Success => write!(f, "success"),
+      Self::Failed { error } => write!(f, "failed: {error}"),
+      Self::Invalid { reason } => write!(f, "invalid: {reason}"),
+      Self::Unavailable { reason } => write!(f, "unavailable: {reason}"),
+    }
+  }
+}
+
+/// What a signed transaction would cost and do, had it been submitted. The fee is serialized in
+/// μPOLYX.
+#[derive(Serialize, Clone, Debug)]
+pub struct DryRunReport {
+  /// Weight of the call, as reported by `payment_queryInfo`
+  pub weight: serde_json::Value,
+  pub class: String,
+  /// Fee excluding the tip
+  pub partial_fee: Balance,
+  pub result: DryRunResult,
+  #[serde(skip)]
+  symbol: String,
+}
+
+impl fmt::Display for DryRunReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "dry run: not submitted")?;
+    writeln!(f, "weight: {} ({})", self.weight, self.class)?;
+    writeln!(f, "fee: {}", self.partial_fee.with_symbol(&self.symbol))?;
+    write!(f, "result: {}", self.result)
+  }
+}
+
+/// A submitted transaction, along with what it did if it was waited for, or a transaction that was
+/// only dry-run
+#[derive(Serialize, Clone, Debug)]
+pub struct TxReceipt {
+  /// Transaction hash as a hexadecimal string with an `0x` prefix
+  pub hash: String,
+  #[serde(flatten)]
+  pub outcome: Option<TxOutcome>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dry_run: Option<DryRunReport>,
+}
+
+impl fmt::Display for TxReceipt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match (&self.outcome, &self.dry_run) {
+      (Some(outcome), _) => write!(f, "hash: {}\n{outcome}", self.hash),
+      (None, Some(dry_run)) => write!(f, "hash: {}\n{dry_run}", self.hash),
+      (None, None) => write!(f, "{}", self.hash),
+    }
+  }
+}
+
+/// Sign and submit a transaction with the nonce, mortality and tip set in `options`, wait for it
+/// as set there too, and return its receipt. Refuses to sign if the node isn't on the chain the
+/// network profile expects, and fails if the transaction fails to dispatch. In a dry run, the
+/// signed transaction is only estimated and dry-run instead.
+pub async fn sign_submit_and_watch(
+  chain: &ChainContext,
+  call: &WrappedCall,
+  signer: &mut impl Signer,
+  options: &TxOptions,
+) -> Result<TxReceipt> {
+  chain.ensure_expected_chain()?;
+  let tx = unsigned_tx(chain, call, signer.account(), options).await?;
+  let extrinsic = tx.sign(&*signer).await?;
+  submit_and_watch(chain, &extrinsic, options).await
+}
+
+/// Prepare `call` for `account` to sign, with the nonce, mortality and tip set in `options`
+pub async fn unsigned_tx(
+  chain: &ChainContext,
+  call: &WrappedCall,
+  account: AccountId,
+  options: &TxOptions,
+) -> Result<UnsignedTx> {
+  let mut tx = UnsignedTx::new(chain, call, account).await?;
+  match options.nonce {
+    Nonce::Auto => {}
+    Nonce::AutoPending => {
+      let client = chain.api.client();
+      let address = chain.ss58check(&account);
+      tx.nonce = client
+        .request("system_accountNextIndex", rpc_params!(address))
+        .await?;
+    }
+    Nonce::Exact(nonce) => tx.nonce = nonce,
+  }
+  if let Mortality::Blocks(blocks) = options.mortality {
+    tx.set_mortality(chain, blocks).await?;
+  }
+  tx.tip = options.tip.micro();
+  Ok(tx)
+}
+
+/// Submit a signed, SCALE-encoded extrinsic, wait for it as set in `options`, and return its
+/// receipt. Refuses to submit if the node isn't on the chain the network profile expects, and fails
+/// if the transaction fails to dispatch. In a dry run, the extrinsic is only estimated and dry-run
+/// instead.
+pub async fn submit_and_watch(
+  chain: &ChainContext,
+  extrinsic: &[u8],
+  options: &TxOptions,
+) -> Result<TxReceipt> {
+  let receipt = submit(chain, extrinsic, options).await?;
+  match &receipt.outcome {
+    Some(outcome) if !outcome.success => {
+      let failure = Failure::new(ErrorCode::DispatchFailed, receipt.to_string());
+      Err(failure.with_details(&receipt).into())
+    }
+    _ => Ok(receipt),
+  }
+}
+
+/// Like [`submit_and_watch`], but returns the receipt of a transaction that failed to dispatch
+/// rather than failing
+pub async fn submit(
+  chain: &ChainContext,
+  extrinsic: &[u8],
+  options: &TxOptions,
+) -> Result<TxReceipt> {
+  chain.ensure_expected_chain()?;
+  let tx_hash = blake2_256(extrinsic);
+  let hash = String::from("0x") + &hex::encode(tx_hash);
+  let extrinsic_hex = String::from("0x") + &hex::encode(extrinsic);
+  let client = chain.api.client();
+  if options.dry_run {
+    return Ok(TxReceipt {
+      hash,
+      outcome: None,
+      dry_run: Some(dry_run(chain, extrinsic).await?),
+    });
+  }
+  if options.wait == Wait::None {
+    let _: String = client
+      .request("author_submitExtrinsic", rpc_params!(&extrinsic_hex))
+      .await?;
+    return Ok(TxReceipt {
+      hash,
+      outcome: None,
+      dry_run: None,
+    });
+  }
+  let mut statuses: Subscription<serde_json::Value> = client
+    .subscribe(
+      "author_submitAndWatchExtrinsic",
+      rpc_params!(&extrinsic_hex),
+      "author_unwatchExtrinsic",
+    )
+    .await?;
+  let finalized = options.wait == Wait::Finalized;
+  while let Some(status) = statuses.next().await {
+    // Statuses are either a string, e.g. `ready`, or an object such as `{"inBlock": "0x.."}`
+    let status = status?;
+    let (name, block) = match &status {
+      serde_json::Value::Object(status) => match status.iter().next() {
+        Some((name, block)) => (name.as_str(), block.as_str()),
+        None => continue,
+      },
+      serde_json::Value::String(name) => (name.as_str(), None),
+      _ => continue,
+    };
+    let block_hash = || -> Result<BlockHash> {
+      let block = block.with_context(|| format!("malformed transaction status {status}"))?;
+      let mut bytes = [0; 32];
+      hex::decode_to_slice(block.trim_start_matches("0x"), &mut bytes)?;
+      Ok(BlockHash::from(bytes))
+    };
+    match name {
+      "inBlock" if !finalized => return included(chain, &tx_hash, block_hash()?, false).await,
+      "finalized" => return included(chain, &tx_hash, block_hash()?, true).await,
+      "usurped" | "dropped" | "invalid" | "finalityTimeout" => {
+        let message = format!("transaction {hash} was rejected by the node ({name})");
+        return Err(Failure::new(ErrorCode::Rpc, message).into());
+      }
+      _ => {}
+    }
+  }
+  let message = format!("transaction {hash} was dropped without being included in a block");
+  Err(Failure::new(ErrorCode::Rpc, message).into())
+}
 
-const MAINNET_URL: &str = "wss://mainnet-rpc.polymesh.network";
-const TESTNET_URL: &str = "wss://testnet-rpc.polymesh.live";
+/// The receipt of a transaction included in block `block_hash`
+async fn included(
+  chain: &ChainContext,
+  tx_hash: &[u8],
+  block_hash: BlockHash,
+  finalized: bool,
+) -> Result<TxReceipt> {
+  Ok(TxReceipt {
+    hash: String::from("0x") + &hex::encode(tx_hash),
+    outcome: Some(outcome(chain, tx_hash, block_hash, finalized).await?),
+    dry_run: None,
+  })
+}
 
-/// Get RPC URL for mainnet or testnet
-pub fn url(mainnet: bool) -> &'static str {
-  if mainnet {
-    MAINNET_URL
-  } else {
-    TESTNET_URL
+/// The fee estimate of a transaction, as returned by `payment_queryInfo`. Nodes give the weight
+/// either as a number or as an object of its components, and the fee either as a number or as a
+/// string.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeInfo {
+  weight: serde_json::Value,
+  class: String,
+  partial_fee: serde_json::Value,
+}
+
+/// Estimate the fee of a signed extrinsic and dry-run it, if the node allows it
+async fn dry_run(chain: &ChainContext, extrinsic: &[u8]) -> Result<DryRunReport> {
+  let extrinsic_hex = String::from("0x") + &hex::encode(extrinsic);
+  let client = chain.api.client();
+  let info: FeeInfo = client
+    .request("payment_queryInfo", rpc_params!(&extrinsic_hex))
+    .await?;
+  let partial_fee = match &info.partial_fee {
+    serde_json::Value::Number(fee) => fee.as_u64().map(u128::from),
+    serde_json::Value::String(fee) => match fee.strip_prefix("0x") {
+      Some(fee) => u128::from_str_radix(fee, 16).ok(),
+      None => fee.parse().ok(),
+    },
+    _ => None,
   }
+  .with_context(|| format!("invalid partial fee {}", info.partial_fee))?;
+  let result = match client
+    .request::<String, _>("system_dryRun", rpc_params!(&extrinsic_hex))
+    .await
+  {
+    Ok(result) => {
+      let bytes = hex::decode(result.trim_start_matches("0x"))?;
+      let metadata = Metadata::fetch(chain, None).await?;
+      dry_run_result(&metadata, &bytes)?
+    }
+    Err(err) => DryRunResult::Unavailable {
+      reason: err.to_string(),
+    },
+  };
+  Ok(DryRunReport {
+    weight: info.weight,
+    class: info.class,
+    partial_fee: Balance::from_micro(partial_fee),
+    result,
+    symbol: chain.symbol.clone(),
+  })
+}
+
+/// Decode the SCALE-encoded `ApplyExtrinsicResult` returned by `system_dryRun`
+fn dry_run_result(metadata: &Metadata, bytes: &[u8]) -> Result<DryRunResult> {
+  let decode = |bytes: &[u8], path: &[&str]| {
+    let ty = metadata
+      .type_id(path)
+      .with_context(|| format!("no {} type in the metadata", path.join("::")))?;
+    metadata.decode_value(bytes, ty)
+  };
+  Ok(match bytes {
+    [0, 0, ..] => DryRunResult::Success,
+    [0, 1, error @ ..] => DryRunResult::Failed {
+      error: metadata.dispatch_error(&decode(error, &["sp_runtime", "DispatchError"])?),
+    },
+    [1, error @ ..] => {
+      let path = [
+        "sp_runtime",
+        "transaction_validity",
+        "TransactionValidityError",
+      ];
+      DryRunResult::Invalid {
+        reason: decode(error, &path)?.to_string(),
+      }
+    }
+    _ => bail!("invalid dry run result 0x{}", hex::encode(bytes)),
+  })
 }
 
-pub fn pairsigner_from_private_key(key: &str) -> Result<PairSigner<sr25519::Pair>> {
-  let pair = sr25519::Pair::from_seed_slice(&hex::decode(key)?)?;
-  Ok(PairSigner::new(pair))
+/// The extrinsics of a block, as returned by `chain_getBlock`
+#[derive(Deserialize)]
+struct SignedBlock {
+  block: Block,
 }
 
-pub fn pairsigner_from_mnemonic(
-  mnemonic: &str,
-  password_override: Option<&str>,
-) -> Result<PairSigner<sr25519::Pair>> {
-  Ok(PairSigner::from_string(mnemonic, password_override)?)
+#[derive(Deserialize)]
+struct Block {
+  /// Hexadecimal SCALE-encoded extrinsics
+  extrinsics: Vec<String>,
 }
 
-/// Sign and submit a transaction, returning the hash as a hexadecimal string with an `0x` prefix.
-pub async fn sign_submit_and_watch(call: &WrappedCall, signer: &mut impl Signer) -> Result<String> {
-  Ok(String::from("0x") + &hex::encode(call.execute(signer).await?.hash()))
+/// Find the extrinsic with hash `tx_hash` in block `block_hash`, and read what it did from the
+/// events it emitted
+async fn outcome(
+  chain: &ChainContext,
+  tx_hash: &[u8],
+  block_hash: BlockHash,
+  finalized: bool,
+) -> Result<TxOutcome> {
+  let block_hex = String::from("0x") + &hex::encode(block_hash);
+  let client = chain.api.client();
+  let block: SignedBlock = client
+    .request("chain_getBlock", rpc_params!(&block_hex))
+    .await?;
+  let extrinsic_index = block
+    .block
+    .extrinsics
+    .iter()
+    .position(|xt| {
+      let xt = hex::decode(xt.trim_start_matches("0x")).unwrap_or_default();
+      blake2_256(&xt)[..] == *tx_hash
+    })
+    .with_context(|| format!("the transaction isn't in block {block_hex}"))?
+    as u32;
+
+  let metadata = Metadata::fetch(chain, Some(block_hash)).await?;
+  let events: Vec<EventReport> = metadata
+    .events(chain, block_hash)
+    .await?
+    .into_iter()
+    .filter(|record| record.extrinsic_index == Some(extrinsic_index))
+    .map(|record| record.event)
+    .collect();
+  let find = |pallet: &str, name: &str| {
+    events
+      .iter()
+      .find(|event| event.pallet == pallet && event.name == name)
+  };
+  let error = find("System", "ExtrinsicFailed").map(|failed| {
+    let fields = &failed.fields;
+    // Older runtimes have unnamed fields, with the error first
+    match fields.at("dispatch_error").or_else(|| fields.at(0)) {
+      Some(error) => metadata.dispatch_error(error),
+      None => DispatchErrorReport {
+        name: String::from("unknown error"),
+        docs: String::new(),
+      },
+    }
+  });
+  let fee = find("TransactionPayment", "TransactionFeePaid")
+    .and_then(|paid| paid.fields.at("actual_fee")?.as_u128())
+    .map(Balance::from_micro);
+  Ok(TxOutcome {
+    block_hash: block_hex,
+    finalized,
+    extrinsic_index,
+    success: error.is_none(),
+    error,
+    fee,
+    events,
+    symbol: chain.symbol.clone(),
+  })
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::network::Network;
   use polymesh_api::Api;
 
   #[tokio::test]
   async fn it_gets_api_on_mainnet() {
-    let api = Api::new(url(true)).await;
+    let api = Api::new(&Network::mainnet().url).await;
     assert!(api.is_ok());
   }
 
   #[tokio::test]
   async fn it_gets_api_on_testnet() {
-    let api = Api::new(url(false)).await;
+    let api = Api::new(&Network::testnet().url).await;
     assert!(api.is_ok());
   }
+
+  #[test]
+  fn it_describes_transaction_outcomes() {
+    let event = |pallet: &str, name: &str| EventReport {
+      pallet: String::from(pallet),
+      name: String::from(name),
+      fields: scale_value::Composite::Unnamed(Vec::new()),
+    };
+    let receipt = TxReceipt {
+      hash: String::from("0x01"),
+      outcome: Some(TxOutcome {
+        block_hash: String::from("0x02"),
+        finalized: false,
+        extrinsic_index: 3,
+        success: false,
+        error: Some(DispatchErrorReport {
+          name: String::from("Balances.InsufficientBalance"),
+          docs: String::from("Balance too low to send value"),
+        }),
+        fee: Some(Balance::from_micro(25_000)),
+        events: vec![event("System", "ExtrinsicFailed")],
+        symbol: String::from("POLYX"),
+      }),
+      dry_run: None,
+    };
+    let expected = "hash: 0x01\nblock: 0x02 (in block)\nextrinsic: 3\nfee: 0.025 POLYX\n\
+      result: failed: Balances.InsufficientBalance: Balance too low to send value\n\
+      events:\n  System.ExtrinsicFailed";
+    assert_eq!(receipt.to_string(), expected);
+    let json = serde_json::to_value(&receipt).unwrap();
+    assert_eq!(json["extrinsic_index"], 3);
+    assert_eq!(json["error"]["name"], "Balances.InsufficientBalance");
+  }
+
+  #[test]
+  fn it_parses_nonces_and_mortalities() {
+    assert_eq!("auto".parse::<Nonce>().unwrap(), Nonce::Auto);
+    assert_eq!("auto-pending".parse::<Nonce>().unwrap(), Nonce::AutoPending);
+    assert_eq!("42".parse::<Nonce>().unwrap(), Nonce::Exact(42));
+    assert!("-1".parse::<Nonce>().is_err());
+    assert_eq!(
+      "immortal".parse::<Mortality>().unwrap(),
+      Mortality::Immortal
+    );
+    assert_eq!("64".parse::<Mortality>().unwrap(), Mortality::Blocks(64));
+    assert!("0".parse::<Mortality>().is_err());
+  }
+
+  #[test]
+  fn it_describes_dry_runs() {
+    let receipt = TxReceipt {
+      hash: String::from("0x01"),
+      outcome: None,
+      dry_run: Some(DryRunReport {
+        weight: serde_json::json!(1_250_000),
+        class: String::from("normal"),
+        partial_fee: Balance::from_micro(25_000),
+        result: DryRunResult::Unavailable {
+          reason: String::from("RPC call is unsafe to be called externally"),
+        },
+        symbol: String::from("POLYX"),
+      }),
+    };
+    let expected = "hash: 0x01\ndry run: not submitted\nweight: 1250000 (normal)\n\
+      fee: 0.025 POLYX\nresult: unavailable: RPC call is unsafe to be called externally";
+    assert_eq!(receipt.to_string(), expected);
+    let json = serde_json::to_value(&receipt).unwrap();
+    assert_eq!(json["dry_run"]["result"]["status"], "unavailable");
+  }
+
+  #[tokio::test]
+  async fn it_decodes_dry_run_results() {
+    let network = crate::network::Network::testnet();
+    let chain = ChainContext::connect(&network).await.unwrap();
+    let metadata = Metadata::fetch(&chain, None).await.unwrap();
+    assert!(matches!(
+      dry_run_result(&metadata, &[0, 0]).unwrap(),
+      DryRunResult::Success
+    ));
+    // Module error 0 of the System pallet
+    match dry_run_result(&metadata, &[0, 1, 3, 0, 0, 0, 0, 0]).unwrap() {
+      DryRunResult::Failed { error } => assert!(error.name.starts_with("System.")),
+      result => panic!("unexpected {result}"),
+    }
+    // `InvalidTransaction::Stale`
+    match dry_run_result(&metadata, &[1, 0, 3]).unwrap() {
+      DryRunResult::Invalid { reason } => assert!(reason.contains("Stale")),
+      result => panic!("unexpected {result}"),
+    }
+  }
+
+  #[test]
+  fn it_derives_signers_from_a_path_and_password() {
+    let key = "6282c8c97534f8570573ccd4136539b2be1db1dc5b35e224c4db2b51d29c653e";
+    let spec = SignerSpec::from(SignerSource::PrivateKey(String::from(key)));
+    let root = signer(&spec).unwrap().account();
+    let derived = SignerSpec {
+      derivation: Some(String::from("//polymesh//0")),
+      ..spec.clone()
+    };
+    assert_ne!(signer(&derived).unwrap().account(), root);
+
+    let mnemonic = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+    let spec = SignerSpec::from(SignerSource::Mnemonic(String::from(mnemonic)));
+    let with_password = SignerSpec {
+      password: Some(String::from("secret")),
+      ..spec.clone()
+    };
+    assert_ne!(
+      signer(&with_password).unwrap().account(),
+      signer(&spec).unwrap().account()
+    );
+  }
 }